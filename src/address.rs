@@ -0,0 +1,148 @@
+use std::fmt;
+use std::str::FromStr;
+use bech32::{Bech32m, Hrp};
+use bech32::primitives::decode::CheckedHrpstring;
+use ed25519_dalek::PublicKey;
+use sha2::{Sha256, Digest};
+use crate::chain_id::ChainId;
+
+// Addresses commit to the first 20 bytes of SHA-256 over the ed25519 public key
+pub const ADDRESS_LEN: usize = 20;
+
+// Human-readable part per network, so an address can't be pasted into the wrong one
+fn hrp(chain_id: ChainId) -> &'static str {
+    match chain_id {
+        ChainId::Mainnet => "cc",
+        ChainId::Testnet => "tcc",
+        ChainId::Devnet => "dcc",
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AddressError {
+    Invalid(String),
+    UnknownPrefix(String),
+    WrongNetwork { expected: ChainId, found: ChainId },
+    WrongLength(usize),
+    NotCanonical,
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressError::Invalid(reason) => write!(f, "invalid address: {}", reason),
+            AddressError::UnknownPrefix(prefix) => write!(f, "unknown address prefix '{}'", prefix),
+            AddressError::WrongNetwork { expected, found } => {
+                write!(f, "address is for {}, expected {}", found, expected)
+            }
+            AddressError::WrongLength(len) => write!(f, "address payload is {} bytes, expected {}", len, ADDRESS_LEN),
+            AddressError::NotCanonical => write!(f, "address must be written in lowercase"),
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+/// A Cacia account address.
+///
+/// Displayed as bech32m with a per-network prefix (`cc1...` on mainnet,
+/// `tcc1...` on testnet, `dcc1...` on devnet); the checksum catches typos
+/// before funds are sent anywhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Address {
+    chain_id: ChainId,
+    hash: [u8; ADDRESS_LEN],
+}
+
+impl Address {
+    pub fn from_public_key(public_key: &PublicKey, chain_id: ChainId) -> Self {
+        let mut hash = [0u8; ADDRESS_LEN];
+        hash.copy_from_slice(&Sha256::digest(public_key.as_bytes())[..ADDRESS_LEN]);
+        Address { chain_id, hash }
+    }
+
+    // Parse an address and check it belongs to the given network
+    pub fn parse(s: &str, chain_id: ChainId) -> Result<Self, AddressError> {
+        let address: Address = s.parse()?;
+        if address.chain_id != chain_id {
+            return Err(AddressError::WrongNetwork { expected: chain_id, found: address.chain_id });
+        }
+        Ok(address)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let encoded = bech32::encode::<Bech32m>(Hrp::parse_unchecked(hrp(self.chain_id)), &self.hash)
+            .map_err(|_| fmt::Error)?;
+        f.write_str(&encoded)
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let checked = CheckedHrpstring::new::<Bech32m>(s).map_err(|e| AddressError::Invalid(e.to_string()))?;
+        let prefix = checked.hrp().to_lowercase();
+        let chain_id = [ChainId::Mainnet, ChainId::Testnet, ChainId::Devnet]
+            .into_iter()
+            .find(|c| hrp(*c) == prefix)
+            .ok_or(AddressError::UnknownPrefix(prefix))?;
+
+        let bytes: Vec<u8> = checked.byte_iter().collect();
+        let hash: [u8; ADDRESS_LEN] = bytes.as_slice().try_into().map_err(|_| AddressError::WrongLength(bytes.len()))?;
+        let address = Address { chain_id, hash };
+
+        // Balances are keyed by the display form, so only accept that exact spelling
+        if address.to_string() != s {
+            return Err(AddressError::NotCanonical);
+        }
+        Ok(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::keypair;
+
+    fn devnet_address() -> Address {
+        Address::from_public_key(&keypair(1).public, ChainId::Devnet)
+    }
+
+    #[test]
+    fn round_trips_through_its_display_form() {
+        let address = devnet_address();
+        let shown = address.to_string();
+        assert!(shown.starts_with("dcc1"));
+        assert_eq!(Address::parse(&shown, ChainId::Devnet), Ok(address));
+    }
+
+    #[test]
+    fn single_character_typo_fails_the_checksum() {
+        let shown = devnet_address().to_string();
+        let position = shown.len() - 10;
+        let typo = if &shown[position..position + 1] == "q" { "p" } else { "q" };
+        let mistyped = format!("{}{}{}", &shown[..position], typo, &shown[position + 1..]);
+        assert!(matches!(Address::parse(&mistyped, ChainId::Devnet), Err(AddressError::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_other_networks_spellings_and_lengths() {
+        let testnet = Address::from_public_key(&keypair(1).public, ChainId::Testnet).to_string();
+        assert_eq!(
+            Address::parse(&testnet, ChainId::Devnet),
+            Err(AddressError::WrongNetwork { expected: ChainId::Devnet, found: ChainId::Testnet })
+        );
+
+        let upper = devnet_address().to_string().to_uppercase();
+        assert_eq!(Address::parse(&upper, ChainId::Devnet), Err(AddressError::NotCanonical));
+
+        let short = bech32::encode::<Bech32m>(Hrp::parse_unchecked(hrp(ChainId::Devnet)), &[7; ADDRESS_LEN - 1]).unwrap();
+        assert_eq!(Address::parse(&short, ChainId::Devnet), Err(AddressError::WrongLength(ADDRESS_LEN - 1)));
+
+        let unknown = bech32::encode::<Bech32m>(Hrp::parse_unchecked("xcc"), &[7; ADDRESS_LEN]).unwrap();
+        assert_eq!(Address::parse(&unknown, ChainId::Devnet), Err(AddressError::UnknownPrefix("xcc".into())));
+    }
+}
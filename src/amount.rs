@@ -0,0 +1,173 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// CC amounts are fixed-point with 8 decimal places
+pub const DECIMALS: usize = 8;
+pub const COIN: u64 = 10_u64.pow(DECIMALS as u32);
+
+#[derive(Debug, PartialEq)]
+pub enum AmountError {
+    Empty,
+    InvalidDigit,
+    TooManyDecimals,
+    Overflow,
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AmountError::Empty => write!(f, "amount is empty"),
+            AmountError::InvalidDigit => write!(f, "amount must be a decimal number like 12 or 0.5"),
+            AmountError::TooManyDecimals => write!(f, "amount has more than {} decimal places", DECIMALS),
+            AmountError::Overflow => write!(f, "amount is too large"),
+        }
+    }
+}
+
+impl std::error::Error for AmountError {}
+
+/// A quantity of CC, held as an integer number of base units (10^-8 CC).
+///
+/// Arithmetic is checked so a balance can never wrap. Amounts are written as
+/// decimal strings (`"12.5"`), including in JSON, so large values survive
+/// clients that parse numbers as doubles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(u64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const MAX: Amount = Amount(u64::MAX);
+
+    pub const fn from_base_units(units: u64) -> Self {
+        Amount(units)
+    }
+
+    // Panics on overflow, so only for constants and other known-small values
+    pub const fn from_coins(coins: u64) -> Self {
+        Amount(coins * COIN)
+    }
+
+    pub const fn base_units(self) -> u64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    pub fn checked_mul(self, factor: u64) -> Option<Amount> {
+        self.0.checked_mul(factor).map(Amount)
+    }
+
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_add(other.0))
+    }
+
+    // Sum a sequence of amounts, None if the total overflows
+    pub fn checked_sum<I: IntoIterator<Item = Amount>>(amounts: I) -> Option<Amount> {
+        amounts.into_iter().try_fold(Amount::ZERO, Amount::checked_add)
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (whole, fraction) = (self.0 / COIN, self.0 % COIN);
+        if fraction == 0 {
+            return write!(f, "{}", whole);
+        }
+        let digits = format!("{:0width$}", fraction, width = DECIMALS);
+        write!(f, "{}.{}", whole, digits.trim_end_matches('0'))
+    }
+}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        if whole.is_empty() {
+            return Err(if s.is_empty() { AmountError::Empty } else { AmountError::InvalidDigit });
+        }
+        if s.ends_with('.') || !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+            return Err(AmountError::InvalidDigit);
+        }
+        if fraction.len() > DECIMALS {
+            return Err(AmountError::TooManyDecimals);
+        }
+
+        let whole: u64 = whole.parse().map_err(|_| AmountError::Overflow)?;
+        let fraction: u64 = format!("{:0<width$}", fraction, width = DECIMALS).parse().unwrap();
+        whole
+            .checked_mul(COIN)
+            .and_then(|units| units.checked_add(fraction))
+            .map(Amount)
+            .ok_or(AmountError::Overflow)
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<Amount, AmountError> {
+        s.parse()
+    }
+
+    #[test]
+    fn parses_decimal_strings_up_to_eight_places() {
+        assert_eq!(parse("0.5"), Ok(Amount::from_base_units(50_000_000)));
+        assert_eq!(parse("12"), Ok(Amount::from_coins(12)));
+        assert_eq!(parse("0.00000001"), Ok(Amount::from_base_units(1)));
+        assert_eq!(parse(".5"), Err(AmountError::InvalidDigit));
+        assert_eq!(parse("1."), Err(AmountError::InvalidDigit));
+        assert_eq!(parse("-1"), Err(AmountError::InvalidDigit));
+        assert_eq!(parse(""), Err(AmountError::Empty));
+        assert_eq!(parse("0.000000001"), Err(AmountError::TooManyDecimals));
+    }
+
+    #[test]
+    fn rejects_amounts_past_u64_max() {
+        assert_eq!(parse("184467440737.09551615"), Ok(Amount::MAX));
+        assert_eq!(parse("184467440737.09551616"), Err(AmountError::Overflow));
+        assert_eq!(parse("184467440738"), Err(AmountError::Overflow));
+        assert_eq!(parse("99999999999999999999999"), Err(AmountError::Overflow));
+        assert_eq!(Amount::MAX.checked_add(Amount::from_base_units(1)), None);
+    }
+
+    #[test]
+    fn displays_without_trailing_zeros() {
+        assert_eq!(Amount::from_coins(100).to_string(), "100");
+        assert_eq!(parse("2.10").unwrap().to_string(), "2.1");
+        assert_eq!(parse("0.50000000").unwrap().to_string(), "0.5");
+        assert_eq!(Amount::from_base_units(1).to_string(), "0.00000001");
+        assert_eq!(Amount::MAX.to_string(), "184467440737.09551615");
+    }
+
+    #[test]
+    fn json_uses_decimal_strings() {
+        let amount = parse("12.5").unwrap();
+        let json = serde_json::to_string(&amount).unwrap();
+        assert_eq!(json, "\"12.5\"");
+        assert_eq!(serde_json::from_str::<Amount>(&json).unwrap(), amount);
+        assert!(serde_json::from_str::<Amount>("12.5").is_err());
+        assert!(serde_json::from_str::<Amount>("\"1.123456789\"").is_err());
+    }
+}
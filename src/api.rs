@@ -0,0 +1,174 @@
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use warp::http::StatusCode;
+use warp::reply::{Json, WithStatus};
+use warp::{Filter, Rejection, Reply};
+use crate::address::Address;
+use crate::tx_error::TxError;
+use crate::{Blockchain, Transaction};
+
+// Reply for a rejected request: the error's tag and fields plus a readable message
+#[derive(Serialize)]
+struct TxRejection<'a> {
+    #[serde(flatten)]
+    error: &'a TxError,
+    message: String,
+}
+
+impl<'a> TxRejection<'a> {
+    fn new(error: &'a TxError) -> Self {
+        TxRejection { error, message: error.to_string() }
+    }
+}
+
+/// Reply for `/nonce`: the confirmed nonce from chain state and the next free
+/// one counting the pool.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AccountNonces {
+    pub confirmed: u64,
+    pub pending: u64,
+}
+
+// Reply for a lookup by account: `lookup`'s result if the address is valid on this chain, else a 400
+fn account_reply<T: Serialize>(bc: &Blockchain, address: &str, lookup: impl FnOnce(&Blockchain) -> T) -> WithStatus<Json> {
+    match Address::parse(address, bc.chain_id) {
+        Ok(_) => warp::reply::with_status(warp::reply::json(&lookup(bc)), StatusCode::OK),
+        Err(e) => {
+            let error = TxError::MalformedAddress { address: address.to_string(), reason: e.to_string() };
+            warp::reply::with_status(warp::reply::json(&TxRejection::new(&error)), error.status())
+        }
+    }
+}
+
+// All of the node's REST endpoints over the shared chain
+pub fn routes(bc: Arc<Mutex<Blockchain>>) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let bc_send = bc.clone();
+    let tx_api = warp::path("send")
+        .and(warp::post())
+        .and(warp::body::json())
+        .map(move |tx: Transaction| {
+            let mut bc_locked = bc_send.lock().unwrap();
+            match bc_locked.add_transaction(tx) {
+                Ok(()) => warp::reply::with_status(warp::reply::json(&"Transaction added"), StatusCode::OK),
+                Err(e) => warp::reply::with_status(warp::reply::json(&TxRejection::new(&e)), e.status()),
+            }
+        });
+
+    let bc_status = bc.clone();
+    let status_api = warp::path("status")
+        .map(move || {
+            let bc_locked = bc_status.lock().unwrap();
+            warp::reply::json(&bc_locked.get_chain())
+        });
+
+    let bc_balance = bc.clone();
+    let balance_api = warp::path!("balance" / String)
+        .map(move |address: String| {
+            let bc_locked = bc_balance.lock().unwrap();
+            account_reply(&bc_locked, &address, |bc| bc.get_balance(&address))
+        });
+
+    let bc_nonce = bc.clone();
+    let nonce_api = warp::path!("nonce" / String)
+        .map(move |address: String| {
+            let bc_locked = bc_nonce.lock().unwrap();
+            account_reply(&bc_locked, &address, |bc| AccountNonces {
+                confirmed: bc.confirmed_nonce(&address),
+                pending: bc.pending_nonce(&address),
+            })
+        });
+
+    let bc_unbonds = bc.clone();
+    let unbonds_api = warp::path!("unbonds" / String)
+        .map(move |address: String| {
+            let bc_locked = bc_unbonds.lock().unwrap();
+            account_reply(&bc_locked, &address, |bc| bc.staking_status(&address))
+        });
+
+    let bc_validators = bc.clone();
+    let validators_api = warp::path("validators")
+        .map(move || {
+            let bc_locked = bc_validators.lock().unwrap();
+            warp::reply::json(&bc_locked.validators())
+        });
+
+    let bc_delegations = bc.clone();
+    let delegations_api = warp::path!("delegations" / String)
+        .map(move |address: String| {
+            let bc_locked = bc_delegations.lock().unwrap();
+            account_reply(&bc_locked, &address, |bc| bc.delegations_of(&address))
+        });
+
+    let bc_epoch = bc.clone();
+    let epoch_api = warp::path("epoch")
+        .map(move || {
+            let bc_locked = bc_epoch.lock().unwrap();
+            warp::reply::json(&bc_locked.epoch_info())
+        });
+
+    let bc_audit = bc.clone();
+    let audit_api = warp::path("audit")
+        .map(move || {
+            let bc_locked = bc_audit.lock().unwrap();
+            warp::reply::json(&bc_locked.audit_supply())
+        });
+
+    let bc_fees = bc.clone();
+    let fees_api = warp::path("fees")
+        .map(move || {
+            let bc_locked = bc_fees.lock().unwrap();
+            warp::reply::json(&bc_locked.estimate_fees())
+        });
+
+    let bc_block = bc.clone();
+    let block_api = warp::path!("block" / String)
+        .map(move |hash: String| {
+            let bc_locked = bc_block.lock().unwrap();
+            warp::reply::json(&bc_locked.find_block(&hash))
+        });
+
+    let bc_tx = bc.clone();
+    let tx_lookup_api = warp::path!("tx" / String)
+        .map(move |tx_hash: String| {
+            let bc_locked = bc_tx.lock().unwrap();
+            warp::reply::json(&bc_locked.find_block_by_tx(&tx_hash))
+        });
+
+    tx_api.or(status_api).or(balance_api).or(nonce_api).or(unbonds_api).or(validators_api).or(delegations_api).or(epoch_api).or(fees_api).or(audit_api).or(block_api).or(tx_lookup_api)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{chain, keypair, transfer};
+
+    async fn send(bc: &Arc<Mutex<Blockchain>>, tx: &Transaction) -> (StatusCode, serde_json::Value) {
+        let reply = warp::test::request().method("POST").path("/send").json(tx).reply(&routes(bc.clone())).await;
+        (reply.status(), serde_json::from_slice(reply.body()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn rejections_carry_the_error_fields_a_message_and_a_status() {
+        let (validator, alice, bob) = (keypair(1), keypair(2), keypair(3));
+        let bc = Arc::new(Mutex::new(chain(&[(&validator, 1_000_000)], &[(&alice, 1_000_000)])));
+
+        let mut tampered = transfer(&alice, &bob, 1_000, 0);
+        tampered.amount = tampered.amount.checked_add(tampered.amount).unwrap();
+        let (status, body) = send(&bc, &tampered).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body, serde_json::json!({"error": "bad_signature", "message": "invalid signature"}));
+
+        let (status, body) = send(&bc, &transfer(&alice, &bob, 1_000, 5)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            body,
+            serde_json::json!({"error": "wrong_nonce", "expected": 0, "got": 5, "message": "incorrect nonce: expected 0, got 5"})
+        );
+
+        let tx = transfer(&alice, &bob, 1_000, 0);
+        assert_eq!(send(&bc, &tx).await.0, StatusCode::OK);
+        let (status, body) = send(&bc, &tx).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body, serde_json::json!({"error": "duplicate", "message": "transaction is already in the pool"}));
+    }
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use crate::amount::Amount;
+use crate::{Blockchain, TOTAL_SUPPLY};
+
+/// Where every unit of the issued supply is at the current tip.
+///
+/// The ledger only moves value between accounts and mints nothing but block
+/// rewards, so balances, stakes, delegated and unbonding stake plus whatever
+/// has been burned must always add up to the genesis supply plus what was minted.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SupplyAudit {
+    pub height: u64,
+    pub issued: Amount,
+    pub minted: Amount,  // Part of `issued` that was minted as block rewards
+    pub balances: Amount,
+    pub stakes: Amount,
+    pub delegated: Amount,
+    pub unbonding: Amount,
+    pub burned: Amount,
+    pub balanced: bool,
+}
+
+impl Blockchain {
+    pub fn audit_supply(&self) -> SupplyAudit {
+        // An overflowing total can't match the issued supply, so saturating is enough to flag it
+        let balances = Amount::checked_sum(self.balances.values().copied()).unwrap_or(Amount::MAX);
+        let stakes = Amount::checked_sum(self.stakes.values().copied()).unwrap_or(Amount::MAX);
+        let delegated = Amount::checked_sum(self.delegations.values().map(|pool| pool.tokens)).unwrap_or(Amount::MAX);
+        let unbonding = Amount::checked_sum(self.unbonding.values().flatten().map(|unbond| unbond.amount)).unwrap_or(Amount::MAX);
+        let accounted = Amount::checked_sum([balances, stakes, delegated, unbonding, self.burned]);
+        let issued = TOTAL_SUPPLY.checked_add(self.minted);
+        SupplyAudit {
+            height: self.chain.back().map(|block| block.index).unwrap_or(0),
+            issued: issued.unwrap_or(Amount::MAX),
+            minted: self.minted,
+            balances,
+            stakes,
+            delegated,
+            unbonding,
+            burned: self.burned,
+            balanced: issued.is_some() && accounted == issued,
+        }
+    }
+
+    // Replay the chain from the genesis state, auditing the supply at every height
+    pub fn audit_chain(&self) -> Result<(), String> {
+        self.replay_audited().map(|_| ())
+    }
+
+    // The audit's replay, which ends with the same chain and state as ours
+    pub(crate) fn replay_audited(&self) -> Result<Blockchain, String> {
+        let mut replay = self.genesis_replica();
+        for block in self.chain.iter().skip(1) {
+            let audit = replay.audit_supply();
+            if !audit.balanced {
+                return Err(format!("supply does not add up at height {}: {:?}", audit.height, audit));
+            }
+            if !replay.apply_block(block.clone()) {
+                return Err(format!("block {} failed to replay", block.index));
+            }
+        }
+        match replay.audit_supply() {
+            audit if audit.balanced => Ok(replay),
+            audit => Err(format!("supply does not add up at height {}: {:?}", audit.height, audit)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::chain_id::ChainId;
+    use crate::genesis::GenesisSpec;
+
+    #[test]
+    fn flags_value_created_from_nothing() {
+        let stakes = BTreeMap::from([("validator".to_string(), Amount::from_coins(10))]);
+        let balances = BTreeMap::from([("user1".to_string(), Amount::from_coins(5))]);
+        let spec = GenesisSpec::with_treasury(ChainId::Devnet, 0, stakes, balances).unwrap();
+        let mut bc = Blockchain::from_genesis(&spec);
+        assert!(bc.audit_supply().balanced);
+
+        bc.balances.insert("user2".to_string(), Amount::from_coins(1));
+        assert!(!bc.audit_supply().balanced);
+    }
+}
@@ -0,0 +1,61 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+
+/// Which Cacia network a transaction or database belongs to.
+///
+/// The numeric id is part of every signed transaction payload, so a signature
+/// made for one network never verifies on another.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ChainId {
+    Mainnet,
+    Testnet,
+    Devnet,
+}
+
+impl ChainId {
+    pub fn id(self) -> u32 {
+        match self {
+            ChainId::Mainnet => 1,
+            ChainId::Testnet => 2,
+            ChainId::Devnet => 3,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Option<Self> {
+        match id {
+            1 => Some(ChainId::Mainnet),
+            2 => Some(ChainId::Testnet),
+            3 => Some(ChainId::Devnet),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ChainId::Mainnet => "mainnet",
+            ChainId::Testnet => "testnet",
+            ChainId::Devnet => "devnet",
+        }
+    }
+}
+
+impl fmt::Display for ChainId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ChainId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mainnet" => Ok(ChainId::Mainnet),
+            "testnet" => Ok(ChainId::Testnet),
+            "devnet" => Ok(ChainId::Devnet),
+            other => Err(format!("unknown chain '{}'", other)),
+        }
+    }
+}
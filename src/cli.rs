@@ -1,105 +1,260 @@
-use clap::{App, Arg, SubCommand};
-use tokio;
+use clap::{Arg, Command};
 use std::{fs, io::Write};
-use ed25519_dalek::{Keypair, PublicKey, SecretKey};
-use rand::rngs::OsRng;
+use std::fs::OpenOptions;
+use std::os::unix::fs::OpenOptionsExt;
+use ed25519_dalek::{PublicKey, SecretKey};
+use cacia::generate_keypair;
+use cacia::address::Address;
+use cacia::amount::Amount;
+use cacia::audit::SupplyAudit;
+use cacia::chain_id::ChainId;
+use cacia::delegation::ValidatorInfo;
+use cacia::epoch::{EpochInfo, ValidatorSet};
+use cacia::staking::StakingStatus;
+use cacia::wallet;
 
 #[tokio::main]
 async fn main() {
-    let matches = App::new("Cacia (CC) CLI")
+    let matches = Command::new("Cacia (CC) CLI")
         .version("0.1.0")
         .author("Zone-crypto-ZNE")
         .about("Cacia cryptocurrency command-line tool")
+        .arg(Arg::new("chain")
+            .help("Network the addresses belong to (mainnet, testnet or devnet)")
+            .long("chain")
+            .default_value("devnet")
+            .global(true))
+        .arg(Arg::new("node")
+            .help("HTTP API of the node to talk to (defaults to the network's API address, or CACIA_API_ADDR)")
+            .long("node")
+            .global(true))
         .subcommand(
-            SubCommand::with_name("balance")
+            Command::new("balance")
                 .about("Check the balance of a Cacia wallet")
-                .arg(Arg::with_name("wallet")
-                    .help("The wallet address or username")
+                .arg(Arg::new("wallet")
+                    .help("The wallet address")
                     .required(true)
                     .index(1)),
         )
         .subcommand(
-            SubCommand::with_name("send")
+            Command::new("send")
                 .about("Send Cacia to another wallet")
-                .arg(Arg::with_name("from")
-                    .help("The sending wallet address")
+                .arg(Arg::new("from")
+                    .help("Name of the sending wallet, whose key in ./wallets signs the transaction")
                     .required(true)
                     .index(1))
-                .arg(Arg::with_name("to")
+                .arg(Arg::new("to")
                     .help("The receiving wallet address")
                     .required(true)
                     .index(2))
-                .arg(Arg::with_name("amount")
-                    .help("Amount of Cacia to send")
+                .arg(Arg::new("amount")
+                    .help("Amount of Cacia to send, in CC with up to 8 decimal places")
                     .required(true)
                     .index(3)),
         )
         .subcommand(
-            SubCommand::with_name("create_account")
+            Command::new("audit")
+                .about("Check that the node's balances, stakes, delegations, unbonding and burned coins add up to the issued supply"),
+        )
+        .subcommand(
+            Command::new("unbonds")
+                .about("Show a wallet's stake and the stake it has waiting to unbond")
+                .arg(Arg::new("wallet")
+                    .help("The wallet address")
+                    .required(true)
+                    .index(1)),
+        )
+        .subcommand(
+            Command::new("validators")
+                .about("List the validators with their own and delegated stake and commission"),
+        )
+        .subcommand(
+            Command::new("epoch")
+                .about("Show the validator set of the current epoch and the one the next epoch would start with"),
+        )
+        .subcommand(
+            Command::new("create_account")
                 .about("Create a new Cacia wallet account")
-                .arg(Arg::with_name("wallet_name")
+                .arg(Arg::new("wallet_name")
                     .help("The name to assign to the wallet")
                     .required(true)
                     .index(1)),
         )
         .get_matches();
 
+    let chain_id: ChainId = match matches.get_one::<String>("chain").unwrap().parse() {
+        Ok(chain_id) => chain_id,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+    let api = match wallet::node_api(matches.get_one::<String>("node"), chain_id) {
+        Ok(api) => api,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+
     match matches.subcommand() {
-        ("balance", Some(sub_matches)) => {
-            let wallet = sub_matches.value_of("wallet").unwrap();
-            check_balance(wallet).await;
+        Some(("balance", sub_matches)) => {
+            let wallet = sub_matches.get_one::<String>("wallet").unwrap();
+            check_balance(&api, wallet, chain_id).await;
+        }
+        Some(("send", sub_matches)) => {
+            let from_wallet = sub_matches.get_one::<String>("from").unwrap();
+            let to_wallet = sub_matches.get_one::<String>("to").unwrap();
+            let amount = sub_matches.get_one::<String>("amount").unwrap();
+            send_transaction(&api, from_wallet, to_wallet, amount, chain_id).await;
+        }
+        Some(("audit", _)) => {
+            audit_supply(&api).await;
+        }
+        Some(("unbonds", sub_matches)) => {
+            let wallet = sub_matches.get_one::<String>("wallet").unwrap();
+            show_unbonds(&api, wallet, chain_id).await;
         }
-        ("send", Some(sub_matches)) => {
-            let from_wallet = sub_matches.value_of("from").unwrap();
-            let to_wallet = sub_matches.value_of("to").unwrap();
-            let amount = sub_matches.value_of("amount").unwrap();
-            send_transaction(from_wallet, to_wallet, amount).await;
+        Some(("validators", _)) => {
+            list_validators(&api).await;
         }
-        ("create_account", Some(sub_matches)) => {
-            let wallet_name = sub_matches.value_of("wallet_name").unwrap();
-            create_account(wallet_name).await;
+        Some(("epoch", _)) => {
+            show_epoch(&api).await;
+        }
+        Some(("create_account", sub_matches)) => {
+            let wallet_name = sub_matches.get_one::<String>("wallet_name").unwrap();
+            create_account(wallet_name, chain_id).await;
         }
         _ => eprintln!("Invalid subcommand."),
     }
 }
 
-async fn check_balance(wallet: &str) {
-    // Placeholder balance check (example: read from a file)
-    let balance = get_balance_from_file(wallet).await;
-    println!("Balance for wallet {}: {} CC", wallet, balance);
+async fn check_balance(api: &str, wallet: &str, chain_id: ChainId) {
+    let address = match Address::parse(wallet, chain_id) {
+        Ok(address) => address,
+        Err(err) => {
+            eprintln!("Invalid wallet address {}: {}", wallet, err);
+            return;
+        }
+    };
+    match wallet::fetch_balance(api, &address).await {
+        Ok(balance) => println!("Balance for wallet {}: {} CC", address, balance),
+        Err(err) => eprintln!("Error checking balance: {}", err),
+    }
 }
 
-async fn send_transaction(from: &str, to: &str, amount: &str) {
-    println!("Sending {} CC from {} to {}", amount, from, to);
-    let result = process_transaction(from, to, amount).await;
-    match result {
-        Ok(_) => println!("Transaction sent successfully!"),
+async fn send_transaction(api: &str, from_wallet: &str, to: &str, amount: &str, chain_id: ChainId) {
+    let amount: Amount = match amount.parse() {
+        Ok(amount) => amount,
+        Err(err) => {
+            eprintln!("Invalid amount {}: {}", amount, err);
+            return;
+        }
+    };
+    let keypair = match wallet::load_keypair(from_wallet) {
+        Ok(keypair) => keypair,
+        Err(err) => {
+            eprintln!("Error loading wallet {}: {}", from_wallet, err);
+            return;
+        }
+    };
+    println!("Sending {} CC from {} to {}", amount, Address::from_public_key(&keypair.public, chain_id), to);
+    match wallet::send(api, &keypair, chain_id, to, amount).await {
+        Ok(tx) => println!("Transaction sent with fee {} CC and nonce {}: {}", tx.fee, tx.nonce, hex::encode(tx.hash())),
         Err(err) => eprintln!("Error sending transaction: {}", err),
     }
 }
 
-async fn get_balance_from_file(wallet: &str) -> u64 {
-    // Example: Fetch balance from a file (you can replace this with actual blockchain integration)
-    let path = format!("./wallets/{}.balance", wallet);
-    if let Ok(contents) = fs::read_to_string(path) {
-        contents.trim().parse().unwrap_or(0)
+async fn audit_supply(api: &str) {
+    let audit: SupplyAudit = match wallet::get(api, "audit").await {
+        Ok(audit) => audit,
+        Err(err) => {
+            eprintln!("Error auditing supply: {}", err);
+            return;
+        }
+    };
+
+    println!("Height:    {}", audit.height);
+    println!("Issued:    {} CC", audit.issued);
+    println!("Minted:    {} CC", audit.minted);
+    println!("Balances:  {} CC", audit.balances);
+    println!("Stakes:    {} CC", audit.stakes);
+    println!("Delegated: {} CC", audit.delegated);
+    println!("Unbonding: {} CC", audit.unbonding);
+    println!("Burned:    {} CC", audit.burned);
+    if audit.balanced {
+        println!("Supply is fully accounted for.");
     } else {
-        0
+        eprintln!("Supply mismatch: balances, stakes, delegations, unbonding and burned coins do not add up to the issued supply!");
+        std::process::exit(1);
     }
 }
 
-async fn process_transaction(from: &str, to: &str, amount: &str) -> Result<(), String> {
-    // Placeholder for actual transaction processing logic
-    if amount.parse::<u64>().is_err() {
-        return Err("Invalid amount.".to_string());
+async fn show_unbonds(api: &str, wallet: &str, chain_id: ChainId) {
+    if let Err(err) = Address::parse(wallet, chain_id) {
+        eprintln!("Invalid wallet address {}: {}", wallet, err);
+        return;
+    }
+    let status: StakingStatus = match wallet::get(api, &format!("unbonds/{}", wallet)).await {
+        Ok(status) => status,
+        Err(err) => {
+            eprintln!("Error checking unbonds: {}", err);
+            return;
+        }
+    };
+
+    println!("Staked:       {} CC", status.stake);
+    for unbond in &status.unbonding {
+        println!("Unbonding:    {} CC, released at height {}", unbond.amount, unbond.release_height);
+    }
+    println!("Withdrawable: {} CC", status.withdrawable);
+}
+
+async fn list_validators(api: &str) {
+    let validators: Vec<ValidatorInfo> = match wallet::get(api, "validators").await {
+        Ok(validators) => validators,
+        Err(err) => {
+            eprintln!("Error listing validators: {}", err);
+            return;
+        }
+    };
+
+    for validator in &validators {
+        println!("{}", validator.address);
+        println!("  Weight:     {} CC", validator.weight);
+        println!("  Own stake:  {} CC", validator.stake);
+        println!("  Delegated:  {} CC from {} delegator(s)", validator.delegated, validator.delegators);
+        println!("  Commission: {}.{:02}%", validator.commission_bps / 100, validator.commission_bps % 100);
+        if let Some(height) = validator.jailed_until {
+            println!("  Jailed until height {} for equivocating", height);
+        }
     }
-    Ok(())
 }
 
-async fn create_account(wallet_name: &str) {
+async fn show_epoch(api: &str) {
+    let info: EpochInfo = match wallet::get(api, "epoch").await {
+        Ok(info) => info,
+        Err(err) => {
+            eprintln!("Error checking epoch: {}", err);
+            return;
+        }
+    };
+
+    println!("Next block: {}", info.height);
+    print_validator_set("Current", &info.current);
+    print_validator_set("Next", &info.next);
+}
+
+fn print_validator_set(label: &str, set: &ValidatorSet) {
+    println!("{} epoch {} (from height {}):", label, set.epoch, set.start_height);
+    for (address, weight) in &set.weights {
+        println!("  {} {} CC", address, weight);
+    }
+}
+
+async fn create_account(wallet_name: &str, chain_id: ChainId) {
     // Generate the keypair
-    let mut rng = OsRng;
-    let keypair = Keypair::generate(&mut rng);
+    let keypair = generate_keypair();
 
     // Extract the public and private keys
     let private_key: &SecretKey = &keypair.secret;
@@ -108,6 +263,7 @@ async fn create_account(wallet_name: &str) {
     // Convert the public key to hex string
     let public_key_hex = hex::encode(public_key.as_bytes());
     let private_key_hex = hex::encode(private_key.to_bytes());
+    let address = Address::from_public_key(&public_key, chain_id);
 
     // Create a wallet directory if it doesn't exist
     fs::create_dir_all("./wallets").expect("Failed to create wallet directory");
@@ -116,14 +272,22 @@ async fn create_account(wallet_name: &str) {
     let public_key_path = format!("./wallets/{}_public.key", wallet_name);
     let private_key_path = format!("./wallets/{}_private.key", wallet_name);
 
-    // Write the public and private keys to files
+    // Only the owner may read the private key, and an existing wallet is never overwritten
+    let mut private_file = match OpenOptions::new().write(true).create_new(true).mode(0o600).open(&private_key_path) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("Could not create {}: {}", private_key_path, err);
+            return;
+        }
+    };
     let mut public_file = fs::File::create(public_key_path).expect("Failed to create public key file");
-    let mut private_file = fs::File::create(private_key_path).expect("Failed to create private key file");
 
+    // Write the public and private keys to files
     public_file.write_all(public_key_hex.as_bytes()).expect("Failed to write public key");
     private_file.write_all(private_key_hex.as_bytes()).expect("Failed to write private key");
 
     println!("Account created successfully!");
+    println!("Address: {}", address);
     println!("Public Key: {}", public_key_hex);
     println!("Private Key: {}", private_key_hex);
     println!("Keys saved to ./wallets/{}_public.key and ./wallets/{}_private.key", wallet_name, wallet_name);
@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::amount::Amount;
+use crate::staking::{TxKind, Unbond};
+use crate::storage::StateDelta;
+use crate::tx_error::TxError;
+use crate::{Blockchain, Transaction};
+
+// Commission rates are in basis points; a validator can take at most all of its delegators' fees
+pub const MAX_COMMISSION_BPS: u32 = 10_000;
+
+/// Stake delegated to one validator.
+///
+/// Delegators own shares of the pool rather than fixed amounts: fees paid to
+/// the pool raise `tokens` without minting shares, so every delegator's stake
+/// grows in proportion to what they put in. The first delegation into an
+/// empty pool gets one share per base unit.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DelegationPool {
+    pub commission_bps: u32,  // The validator's cut of fees earned by the pool
+    pub tokens: Amount,       // Delegated stake plus the fees it has earned
+    pub shares: u64,          // Total shares issued to delegators
+    pub delegators: BTreeMap<String, u64>,
+}
+
+impl DelegationPool {
+    // Tokens `shares` of the pool are worth, rounded down
+    pub fn value_of(&self, shares: u64) -> Amount {
+        if self.shares == 0 {
+            return Amount::ZERO;
+        }
+        let value = shares as u128 * self.tokens.base_units() as u128 / self.shares as u128;
+        Amount::from_base_units(value as u64)
+    }
+
+    pub fn delegated_by(&self, delegator: &str) -> Amount {
+        self.value_of(self.delegators.get(delegator).copied().unwrap_or(0))
+    }
+
+    // A pool with nothing delegated and the default commission needs no entry in the state
+    pub fn is_empty(&self) -> bool {
+        *self == DelegationPool::default()
+    }
+
+    fn delegate(&mut self, delegator: &str, amount: Amount) -> Result<(), TxError> {
+        // Shares are issued at the current price, rounded down in the pool's favour
+        let shares = match self.shares {
+            0 => amount.base_units() as u128,
+            total => amount.base_units() as u128 * total as u128 / self.tokens.base_units().max(1) as u128,
+        };
+        let shares = u64::try_from(shares).map_err(|_| TxError::AmountOverflow)?;
+        self.tokens = self.tokens.checked_add(amount).ok_or(TxError::AmountOverflow)?;
+        self.shares = self.shares.checked_add(shares).ok_or(TxError::AmountOverflow)?;
+        *self.delegators.entry(delegator.to_string()).or_default() += shares;
+        Ok(())
+    }
+
+    fn undelegate(&mut self, delegator: &str, amount: Amount) -> Result<(), TxError> {
+        let held = self.delegators.get(delegator).copied().unwrap_or(0);
+        let available = self.value_of(held);
+        if amount > available {
+            return Err(TxError::InsufficientDelegation { required: amount, available });
+        }
+        // Shares are redeemed rounded up, so what stays behind is never worth less
+        let shares = (amount.base_units() as u128 * self.shares as u128).div_ceil(self.tokens.base_units().max(1) as u128) as u64;
+        let shares = shares.min(held);
+        self.tokens = self.tokens.checked_sub(amount).ok_or(TxError::AmountOverflow)?;
+        self.shares -= shares;
+        match held - shares {
+            0 => self.delegators.remove(delegator),
+            left => self.delegators.insert(delegator.to_string(), left),
+        };
+        Ok(())
+    }
+}
+
+/// A validator as listed by `/validators`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ValidatorInfo {
+    pub address: String,
+    pub stake: Amount,      // The validator's own stake
+    pub delegated: Amount,
+    pub commission_bps: u32,
+    pub delegators: usize,
+    pub weight: Amount,     // What leader selection counts: own stake plus delegated
+    pub jailed_until: Option<u64>,  // Height a jailed validator is released at
+}
+
+/// One of an account's delegations, as listed by `/delegations`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Delegation {
+    pub validator: String,
+    pub shares: u64,
+    pub value: Amount,
+}
+
+impl Blockchain {
+    pub fn delegation_pool(&self, validator: &str) -> DelegationPool {
+        self.delegations.get(validator).cloned().unwrap_or_default()
+    }
+
+    // A validator's weight as of the latest block, which the next epoch's set is taken from.
+    // Delegations only count while the validator has stake of its own, and a jailed validator has none.
+    pub fn validator_weight(&self, address: &str) -> Amount {
+        self.staged_weight(&StateDelta::default(), address, self.next_height())
+    }
+
+    // The same with the staged delta applied, for a block at `height`
+    pub(crate) fn staged_weight(&self, delta: &StateDelta, address: &str, height: u64) -> Amount {
+        let stake = delta.stakes.get(address).copied().unwrap_or_else(|| self.get_stake(address));
+        let jail = delta.jailed.get(address).or_else(|| self.jailed.get(address));
+        if stake == Amount::ZERO || jail.is_some_and(|jail| jail.release_height > height) {
+            return Amount::ZERO;
+        }
+        let pool = delta.delegations.get(address).or_else(|| self.delegations.get(address));
+        stake.saturating_add(pool.map(|pool| pool.tokens).unwrap_or_default())
+    }
+
+    pub fn validators(&self) -> Vec<ValidatorInfo> {
+        let mut validators: Vec<ValidatorInfo> = self
+            .stakes
+            .iter()
+            .filter(|(_, stake)| **stake > Amount::ZERO)
+            .map(|(address, stake)| {
+                let pool = self.delegation_pool(address);
+                ValidatorInfo {
+                    address: address.clone(),
+                    stake: *stake,
+                    delegated: pool.tokens,
+                    commission_bps: pool.commission_bps,
+                    delegators: pool.delegators.len(),
+                    weight: self.validator_weight(address),
+                    jailed_until: self.jailed.get(address).map(|jail| jail.release_height).filter(|_| self.is_jailed(address)),
+                }
+            })
+            .collect();
+        validators.sort_by(|a, b| b.weight.cmp(&a.weight).then_with(|| a.address.cmp(&b.address)));
+        validators
+    }
+
+    pub fn delegations_of(&self, delegator: &str) -> Vec<Delegation> {
+        let mut delegations: Vec<Delegation> = self
+            .delegations
+            .iter()
+            .filter_map(|(validator, pool)| {
+                let shares = *pool.delegators.get(delegator)?;
+                Some(Delegation { validator: validator.clone(), shares, value: pool.value_of(shares) })
+            })
+            .collect();
+        delegations.sort_by(|a, b| a.validator.cmp(&b.validator));
+        delegations
+    }
+
+    pub(crate) fn staged_pool(&self, delta: &StateDelta, validator: &str) -> DelegationPool {
+        delta.delegations.get(validator).cloned().unwrap_or_else(|| self.delegation_pool(validator))
+    }
+
+    // Pool admission checks for delegation transactions against the confirmed state
+    pub(crate) fn check_delegation(&self, tx: &Transaction) -> Result<(), TxError> {
+        match tx.kind {
+            TxKind::Delegate if self.get_stake(&tx.receiver) == Amount::ZERO => {
+                Err(TxError::NotAValidator { address: tx.receiver.clone() })
+            }
+            TxKind::Undelegate => {
+                let available = self.delegation_pool(&tx.receiver).delegated_by(&tx.sender);
+                match tx.amount > available {
+                    true => Err(TxError::InsufficientDelegation { required: tx.amount, available }),
+                    false => Ok(()),
+                }
+            }
+            TxKind::SetCommission { .. } if tx.receiver != tx.sender => Err(TxError::ReceiverNotSender),
+            TxKind::SetCommission { rate_bps } if rate_bps > MAX_COMMISSION_BPS => Err(TxError::InvalidCommission { rate_bps }),
+            _ => Ok(()),
+        }
+    }
+
+    // Execute a delegate, undelegate or set commission in a block at `height` against the
+    // staged delta. Leaves the delta unchanged if the transaction is invalid.
+    pub(crate) fn execute_delegation(&self, delta: &mut StateDelta, tx: &Transaction, height: u64) -> Result<(), TxError> {
+        let mut pool = self.staged_pool(delta, &tx.receiver);
+        let mut unbonds = None;
+        match tx.kind {
+            TxKind::Delegate => {
+                let stake = delta.stakes.get(&tx.receiver).copied().unwrap_or_else(|| self.get_stake(&tx.receiver));
+                if stake == Amount::ZERO {
+                    return Err(TxError::NotAValidator { address: tx.receiver.clone() });
+                }
+                pool.delegate(&tx.sender, tx.amount)?;
+            }
+            TxKind::Undelegate => {
+                pool.undelegate(&tx.sender, tx.amount)?;
+                let mut list = self.staged_unbonds(delta, &tx.sender);
+                list.push(Unbond { amount: tx.amount, release_height: height + self.params.unbonding_period, unbonded_at: height });
+                unbonds = Some(list);
+            }
+            TxKind::SetCommission { rate_bps } => {
+                if tx.receiver != tx.sender {
+                    return Err(TxError::ReceiverNotSender);
+                }
+                if rate_bps > MAX_COMMISSION_BPS {
+                    return Err(TxError::InvalidCommission { rate_bps });
+                }
+                pool.commission_bps = rate_bps;
+            }
+            _ => unreachable!("only delegation transactions are executed here"),
+        }
+        let debit = tx.balance_debit().ok_or(TxError::AmountOverflow)?;
+        let available = self.staged_balance(delta, &tx.sender);
+        let balance = available.checked_sub(debit).ok_or(TxError::InsufficientBalance { required: debit, available })?;
+
+        delta.balances.insert(tx.sender.clone(), balance);
+        delta.delegations.insert(tx.receiver.clone(), pool);
+        if let Some(unbonds) = unbonds {
+            delta.unbonding.insert(tx.sender.clone(), unbonds);
+        }
+        Ok(())
+    }
+
+    // Pay what a block earned to its proposer, less the delegators' part: the delegated share of
+    // the proposer's weight in the epoch's validator set, the weight the slot was won with, after
+    // commission. That part is added to the pool's tokens, so it compounds into the delegators' stake.
+    pub(crate) fn pay_proposer(&self, delta: &mut StateDelta, proposer: &str, earned: Amount) -> Result<(), TxError> {
+        let set = &self.validator_set;
+        let weight = set.weights.get(proposer).copied().unwrap_or_default().base_units() as u128;
+        let delegated = set.delegated.get(proposer).copied().unwrap_or_default().base_units() as u128;
+        let commission_bps = self.delegation_pool(proposer).commission_bps;
+        let mut pool = self.staged_pool(delta, proposer);
+        // Nobody left to pay if the whole pool undelegated since the epoch began
+        let delegators_part = match weight > 0 && pool.shares > 0 {
+            true => {
+                let gross = earned.base_units() as u128 * delegated / weight;
+                let commission = gross * commission_bps as u128 / MAX_COMMISSION_BPS as u128;
+                Amount::from_base_units((gross - commission) as u64)
+            }
+            false => Amount::ZERO,
+        };
+        if delegators_part > Amount::ZERO {
+            pool.tokens = pool.tokens.checked_add(delegators_part).ok_or(TxError::AmountOverflow)?;
+            delta.delegations.insert(proposer.to_string(), pool);
+        }
+        let proposer_part = earned.checked_sub(delegators_part).ok_or(TxError::AmountOverflow)?;
+        let balance = self.staged_balance(delta, proposer).checked_add(proposer_part).ok_or(TxError::AmountOverflow)?;
+        delta.balances.insert(proposer.to_string(), balance);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{address, chain, delegation, extend, keypair, staking, transfer};
+
+    #[test]
+    fn delegators_earn_fees_after_commission() {
+        let validator = keypair(1);
+        let (alice, bob) = (keypair(2), keypair(3));
+        let mut bc = chain(&[(&validator, 1_000_000)], &[(&validator, 100_000), (&alice, 3_000_000), (&bob, 100_000)]);
+        bc.params.epoch_length = 4;
+        let units = Amount::from_base_units;
+        let v = address(&validator);
+
+        // No delegators yet, so the proposer keeps these fees
+        assert!(extend(&mut bc, &validator, vec![staking(&validator, TxKind::SetCommission { rate_bps: 1_000 }, 0, 0)]));
+        assert!(extend(&mut bc, &validator, vec![delegation(&alice, &validator, TxKind::Delegate, 1_000_000, 0)]));
+        assert_eq!(bc.validator_weight(&v), units(2_000_000));
+        assert_eq!(bc.get_balance(&v), units(110_000));
+
+        // The delegation only counts from the next epoch, so this fee is still all the proposer's
+        assert!(extend(&mut bc, &validator, vec![transfer(&bob, &alice, 1, 0)]));
+        assert_eq!(bc.get_balance(&v), units(120_000));
+        assert_eq!(bc.delegations_of(&address(&alice))[0].value, units(1_000_000));
+
+        // Half the epoch's weight is delegated: 5_000 of the 10_000 fee, less 10% commission
+        assert!(extend(&mut bc, &validator, vec![transfer(&bob, &alice, 1, 1)]));
+        assert_eq!(bc.get_balance(&v), units(125_500));
+        assert_eq!(bc.delegations_of(&address(&alice))[0].value, units(1_004_500));
+        assert_eq!(bc.validator_weight(&v), units(2_004_500));
+
+        assert!(extend(&mut bc, &validator, vec![delegation(&alice, &validator, TxKind::Undelegate, 1_004_500, 1)]));
+        assert!(bc.delegations_of(&address(&alice)).is_empty());
+        assert_eq!(bc.unbonds(&address(&alice))[0].amount, units(1_004_500));
+        assert!(bc.audit_chain().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_delegations() {
+        let validator = keypair(1);
+        let (alice, bob) = (keypair(2), keypair(3));
+        let mut bc = chain(&[(&validator, 1_000_000)], &[(&alice, 1_000_000)]);
+
+        let to_non_validator = delegation(&alice, &bob, TxKind::Delegate, 1_000, 0);
+        assert_eq!(bc.add_transaction(to_non_validator.clone()), Err(TxError::NotAValidator { address: address(&bob) }));
+        assert!(!extend(&mut bc, &validator, vec![to_non_validator]));
+
+        let too_much = delegation(&alice, &validator, TxKind::Undelegate, 1, 0);
+        assert_eq!(
+            bc.add_transaction(too_much),
+            Err(TxError::InsufficientDelegation { required: Amount::from_base_units(1), available: Amount::ZERO })
+        );
+        let commission = staking(&validator, TxKind::SetCommission { rate_bps: 10_001 }, 0, 0);
+        assert_eq!(bc.add_transaction(commission.clone()), Err(TxError::InvalidCommission { rate_bps: 10_001 }));
+        assert!(!extend(&mut bc, &validator, vec![commission]));
+    }
+}
@@ -0,0 +1,557 @@
+use std::fmt;
+use sha2::{Sha256, Digest};
+use crate::{Block, Blockchain, Transaction};
+use crate::amount::Amount;
+use crate::chain_id::ChainId;
+use crate::slashing::BlockHeader;
+use crate::staking::TxKind;
+
+// Layout version new values of each kind are created under. A value keeps the version
+// it was created under and is always written, hashed and signed in that layout, so a
+// bump here never changes the identity of anything that already exists. Decoders read
+// every version up to these.
+//
+// Transactions: 1 had no kind, 2 added the kind, 3 prefixes the block headers in
+// evidence with their own version.
+pub const TX_VERSION: u8 = 3;
+// Blocks: 1 and 2 share a layout and hold transactions in the block's own version;
+// 3 prefixes each transaction with its own version.
+pub const BLOCK_VERSION: u8 = 3;
+// Genesis specs, and the genesis block derived from one, so a network keeps its genesis
+pub const GENESIS_VERSION: u8 = 2;
+
+// Version of transactions and headers in JSON that doesn't name one; those predate
+// per-value versions and were all laid out as version 2
+pub fn unversioned() -> u8 {
+    2
+}
+
+// Domain tags keep a signature over one kind of payload from being valid for another
+pub const TX_DOMAIN: &[u8] = b"cacia/tx";
+pub const BLOCK_DOMAIN: &[u8] = b"cacia/block";
+pub const GENESIS_DOMAIN: &[u8] = b"cacia/genesis";
+
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    UnexpectedEnd,
+    UnsupportedVersion(u8),
+    UnknownTag(u8),
+    UnknownChainId(u32),
+    InvalidUtf8,
+    TrailingBytes,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported encoding version {}", v),
+            DecodeError::UnknownTag(t) => write!(f, "unknown tag {}", t),
+            DecodeError::UnknownChainId(id) => write!(f, "unknown chain id {}", id),
+            DecodeError::InvalidUtf8 => write!(f, "invalid utf-8 in string field"),
+            DecodeError::TrailingBytes => write!(f, "trailing bytes after value"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Writer for the canonical encoding.
+///
+/// Integers are fixed-width big-endian, strings and byte fields carry a u32
+/// big-endian length prefix and sequences a u32 element count, so no two
+/// distinct values share an encoding.
+#[derive(Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+    version: u8,  // Layout version of the versioned value being written
+}
+
+impl Encoder {
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    // Write the parts of a value laid out as `version`, for the parts whose layout depends on it
+    pub fn in_version(&mut self, version: u8, f: impl FnOnce(&mut Self)) {
+        let outer = std::mem::replace(&mut self.version, version);
+        f(self);
+        self.version = outer;
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn i64(&mut self, value: i64) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value);
+    }
+
+    pub fn str(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+
+    pub fn seq<T: Encode>(&mut self, items: &[T]) {
+        self.u32(items.len() as u32);
+        for item in items {
+            item.encode_to(self);
+        }
+    }
+
+    // A value prefixed with its own layout version, so it keeps that layout inside newer ones
+    pub fn versioned<T: Versioned>(&mut self, value: &T) {
+        self.u8(value.version());
+        value.encode_to(self);
+    }
+
+    pub fn versioned_seq<T: Versioned>(&mut self, items: &[T]) {
+        self.u32(items.len() as u32);
+        for item in items {
+            self.versioned(item);
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    version: u8,  // Layout version of the versioned value being read
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Decoder { data, pos: 0, version: 0 }
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self.pos.checked_add(len).ok_or(DecodeError::UnexpectedEnd)?;
+        let slice = self.data.get(self.pos..end).ok_or(DecodeError::UnexpectedEnd)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, DecodeError> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn i64(&mut self) -> Result<i64, DecodeError> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], DecodeError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn str(&mut self) -> Result<String, DecodeError> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    pub fn seq<T: Decode>(&mut self) -> Result<Vec<T>, DecodeError> {
+        self.seq_with(T::decode_from)
+    }
+
+    pub fn versioned<T: Versioned>(&mut self) -> Result<T, DecodeError> {
+        let version = self.u8()?;
+        if version == 0 || version > T::LATEST {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let outer = std::mem::replace(&mut self.version, version);
+        let value = T::decode_from(self);
+        self.version = outer;
+        value
+    }
+
+    pub fn versioned_seq<T: Versioned>(&mut self) -> Result<Vec<T>, DecodeError> {
+        self.seq_with(Self::versioned)
+    }
+
+    fn seq_with<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, DecodeError>) -> Result<Vec<T>, DecodeError> {
+        let count = self.u32()? as usize;
+        // Every element takes at least one byte, which bounds the allocation
+        if count > self.data.len() - self.pos {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let mut items = Vec::with_capacity(count);
+        for _ in 0..count {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+}
+
+pub trait Encode {
+    fn encode_to(&self, enc: &mut Encoder);
+}
+
+pub trait Decode: Sized {
+    fn decode_from(dec: &mut Decoder) -> Result<Self, DecodeError>;
+}
+
+/// A value whose byte layout has changed over time.
+///
+/// Each value carries the layout version it was created under, and decoders
+/// take it from the version prefix or, for values nested in an older layout,
+/// from the enclosing value.
+pub trait Versioned: Encode + Decode {
+    const LATEST: u8;
+
+    fn version(&self) -> u8;
+
+    // Top-level encoding, prefixed with the value's layout version
+    fn encode(&self) -> Vec<u8> {
+        let mut enc = Encoder::default();
+        enc.versioned(self);
+        enc.finish()
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut dec = Decoder::new(data);
+        let value = dec.versioned()?;
+        if dec.pos != data.len() {
+            return Err(DecodeError::TrailingBytes);
+        }
+        Ok(value)
+    }
+}
+
+impl Transaction {
+    // The bytes a sender signs and the transaction id is hashed from: the domain tag
+    // followed by every field but the signature
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut enc = Encoder::default();
+        enc.u8(self.version);
+        enc.bytes(TX_DOMAIN);
+        enc.in_version(self.version, |enc| self.encode_unsigned(enc));
+        enc.finish()
+    }
+
+    // Whether the transaction's layout can express all of it, so its signature covers
+    // everything and peers read back the same transaction
+    pub fn has_valid_layout(&self) -> bool {
+        let headers_fit = match &self.kind {
+            TxKind::Evidence(evidence) => [&evidence.first, &evidence.second].iter().all(|header| match self.version {
+                2 => header.version == 2,
+                _ => (1..=BLOCK_VERSION).contains(&header.version),
+            }),
+            _ => true,
+        };
+        match self.version {
+            1 => self.kind == TxKind::Transfer,
+            version => (2..=TX_VERSION).contains(&version) && headers_fit,
+        }
+    }
+
+    fn encode_unsigned(&self, enc: &mut Encoder) {
+        enc.u32(self.chain_id.id());
+        if self.version >= 2 {
+            self.kind.encode_to(enc);
+        }
+        enc.str(&self.sender);
+        enc.str(&self.receiver);
+        enc.u64(self.amount.base_units());
+        enc.u64(self.fee.base_units());
+        enc.u64(self.nonce);
+        enc.i64(self.timestamp);
+        enc.str(&self.public_key);
+    }
+}
+
+impl Encode for Transaction {
+    fn encode_to(&self, enc: &mut Encoder) {
+        enc.in_version(self.version, |enc| {
+            self.encode_unsigned(enc);
+            enc.str(&self.signature);
+        });
+    }
+}
+
+impl Decode for Transaction {
+    fn decode_from(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let version = dec.version();
+        let chain_id = dec.u32()?;
+        Ok(Transaction {
+            version,
+            chain_id: ChainId::from_id(chain_id).ok_or(DecodeError::UnknownChainId(chain_id))?,
+            kind: if version >= 2 { TxKind::decode_from(dec)? } else { TxKind::Transfer },
+            sender: dec.str()?,
+            receiver: dec.str()?,
+            amount: Amount::from_base_units(dec.u64()?),
+            fee: Amount::from_base_units(dec.u64()?),
+            nonce: dec.u64()?,
+            timestamp: dec.i64()?,
+            public_key: dec.str()?,
+            signature: dec.str()?,
+        })
+    }
+}
+
+impl Versioned for Transaction {
+    const LATEST: u8 = TX_VERSION;
+
+    fn version(&self) -> u8 {
+        self.version
+    }
+}
+
+impl BlockHeader {
+    // The bytes the header hash is computed over; transactions are committed to through their root
+    pub fn header_bytes(&self) -> Vec<u8> {
+        let mut enc = Encoder::default();
+        enc.u8(self.version);
+        enc.bytes(BLOCK_DOMAIN);
+        enc.u64(self.index);
+        enc.u64(self.slot);
+        enc.i64(self.timestamp);
+        enc.str(&self.previous_hash);
+        enc.bytes(&hex::decode(&self.transactions_root).unwrap_or_default());
+        enc.str(&self.validator);
+        enc.str(&self.public_key);
+        enc.finish()
+    }
+}
+
+impl Block {
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            version: self.version,
+            index: self.index,
+            slot: self.slot,
+            timestamp: self.timestamp,
+            previous_hash: self.previous_hash.clone(),
+            transactions_root: hex::encode(self.transactions_root()),
+            validator: self.validator.clone(),
+            public_key: self.public_key.clone(),
+            signature: self.signature.clone(),
+        }
+    }
+
+    pub fn header_bytes(&self) -> Vec<u8> {
+        self.header().header_bytes()
+    }
+
+    pub fn transactions_root(&self) -> Vec<u8> {
+        let mut enc = Encoder::default();
+        self.encode_transactions(&mut enc);
+        Sha256::digest(enc.finish()).to_vec()
+    }
+
+    fn encode_transactions(&self, enc: &mut Encoder) {
+        match self.version {
+            1 | 2 => enc.in_version(self.version, |enc| enc.seq(&self.transactions)),
+            _ => enc.versioned_seq(&self.transactions),
+        }
+    }
+}
+
+// The header hash is not transmitted; decoders recompute it from the other fields
+impl Encode for Block {
+    fn encode_to(&self, enc: &mut Encoder) {
+        enc.u64(self.index);
+        enc.u64(self.slot);
+        enc.i64(self.timestamp);
+        enc.str(&self.previous_hash);
+        enc.str(&self.validator);
+        enc.str(&self.public_key);
+        self.encode_transactions(enc);
+        enc.str(&self.signature);
+    }
+}
+
+impl Decode for Block {
+    fn decode_from(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let version = dec.version();
+        let mut block = Block {
+            version,
+            index: dec.u64()?,
+            slot: dec.u64()?,
+            timestamp: dec.i64()?,
+            previous_hash: dec.str()?,
+            validator: dec.str()?,
+            public_key: dec.str()?,
+            transactions: match version {
+                1 | 2 => dec.seq()?,
+                _ => dec.versioned_seq()?,
+            },
+            signature: dec.str()?,
+            hash: String::new(),
+        };
+        block.hash = Blockchain::hash_block(&block);
+        Ok(block)
+    }
+}
+
+impl Versioned for Block {
+    const LATEST: u8 = BLOCK_VERSION;
+
+    fn version(&self) -> u8 {
+        self.version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A block as stored by a node from before transaction kinds, holding sample_tx(1)
+    const STORED_V1_BLOCK: &str = "010000000000000001000000001443fd00000000006553f100000000403030303030303030303030303030303030303030303030303030303030303030303030\
+         30303030303030303030303030303030303030303030303030303030300000004063636363636363636363636363636363636363636363636363636363636363\
+         63636363636363636363636363636363636363636363636363636363636363636300000040636363636363636363636363636363636363636363636363636363\
+         63636363636363636363636363636363636363636363636363636363636363636363636363000000010000000200000005616c69636500000003626f62000000\
+         0008f0d18000000000000013880000000000000007000000006553f1000000004062626262626262626262626262626262626262626262626262626262626262\
+         62626262626262626262626262626262626262626262626262626262626262626200000080616161616161616161616161616161616161616161616161616161\
+         61616161616161616161616161616161616161616161616161616161616161616161616161616161616161616161616161616161616161616161616161616161\
+         61616161616161616161616161616161616161616161616161616161616161616161616161000000806464646464646464646464646464646464646464646464\
+         64646464646464646464646464646464646464646464646464646464646464646464646464646464646464646464646464646464646464646464646464646464\
+         6464646464646464646464646464646464646464646464646464646464646464646464646464646464";
+
+    fn sample_tx(version: u8) -> Transaction {
+        Transaction {
+            version,
+            chain_id: ChainId::Testnet,
+            kind: TxKind::Transfer,
+            sender: "alice".to_string(),
+            receiver: "bob".to_string(),
+            amount: Amount::from_base_units(150_000_000),
+            fee: Amount::from_base_units(5_000),
+            nonce: 7,
+            signature: "aa".repeat(64),
+            timestamp: 1_700_000_000,
+            public_key: "bb".repeat(32),
+        }
+    }
+
+    fn sample_block(version: u8, transactions: Vec<Transaction>) -> Block {
+        let block = Block {
+            version,
+            index: 1,
+            slot: 340_000_000,
+            timestamp: 1_700_000_000,
+            transactions,
+            previous_hash: "00".repeat(32),
+            hash: String::new(),
+            validator: "cc".repeat(32),
+            public_key: "cc".repeat(32),
+            signature: "dd".repeat(64),
+        };
+        Block { hash: Blockchain::hash_block(&block), ..block }
+    }
+
+    #[test]
+    fn transaction_golden_vectors() {
+        let tx = sample_tx(2);
+        assert_eq!(
+            hex::encode(tx.signing_bytes()),
+            "020000000863616369612f74780000000200\
+             00000005616c69636500000003626f62\
+             0000000008f0d18000000000000013880000000000000007000000006553f100\
+             00000040\
+             62626262626262626262626262626262626262626262626262626262626262626262626262626262626262626262626262626262626262626262626262626262"
+        );
+        assert_eq!(hex::encode(tx.hash()), "3c525ee5c166be1632782d80e60946f8d67ff79bab1ea5c86b9f2043a9ff3ced");
+        assert_eq!(hex::encode(sample_tx(1).hash()), "f9db3c88ab2fe8029abc27183f5cf568d5f945266affc25cb2e1bae4dd6b200e");
+        // Transfers only differ from version 2 in the version byte
+        assert_eq!(sample_tx(3).signing_bytes()[1..], tx.signing_bytes()[1..]);
+    }
+
+    #[test]
+    fn block_golden_vectors() {
+        let v2 = sample_block(2, vec![sample_tx(2)]);
+        assert_eq!(v2.hash, "a8f47c3bb3290c512bcdd8225899afe55e902849cc611437deb2a1af9916c7e9");
+        let v1 = sample_block(1, vec![sample_tx(1)]);
+        assert_eq!(v1.hash, "c44f8760372bf51ca2801149afdc4062e89802837af863e016c5c9786cd8bb59");
+    }
+
+    #[test]
+    fn blocks_stored_under_older_versions_still_decode() {
+        let bytes = hex::decode(STORED_V1_BLOCK).unwrap();
+        let block = Block::decode(&bytes).unwrap();
+        assert_eq!(block.hash, sample_block(1, vec![sample_tx(1)]).hash);
+        assert_eq!((block.version, block.transactions[0].version), (1, 1));
+        assert_eq!(block.transactions[0].hash(), sample_tx(1).hash());
+        assert_eq!(block.encode(), bytes);
+    }
+
+    #[test]
+    fn round_trips() {
+        let tx = sample_tx(TX_VERSION);
+        let decoded = Transaction::decode(&tx.encode()).unwrap();
+        assert_eq!(decoded.encode(), tx.encode());
+
+        // Transactions keep their own version, and so their hash, inside newer blocks
+        let block = sample_block(BLOCK_VERSION, vec![sample_tx(2), tx]);
+        let decoded = Block::decode(&block.encode()).unwrap();
+        assert_eq!(decoded.hash, block.hash);
+        assert_eq!(decoded.transactions[0].version, 2);
+        assert_eq!(decoded.transactions[0].hash(), sample_tx(2).hash());
+        assert_eq!(decoded.encode(), block.encode());
+    }
+
+    #[test]
+    fn field_boundaries_are_unambiguous() {
+        let a = Transaction { sender: "ab".to_string(), receiver: "c".to_string(), ..sample_tx(TX_VERSION) };
+        let b = Transaction { sender: "a".to_string(), receiver: "bc".to_string(), ..sample_tx(TX_VERSION) };
+        assert_ne!(a.hash(), b.hash());
+    }
+
+    #[test]
+    fn chain_id_is_signed() {
+        let testnet = sample_tx(TX_VERSION);
+        let mainnet = Transaction { chain_id: ChainId::Mainnet, ..sample_tx(TX_VERSION) };
+        assert_ne!(testnet.hash(), mainnet.hash());
+    }
+
+    #[test]
+    fn kind_is_signed() {
+        let transfer = sample_tx(TX_VERSION);
+        let stake = Transaction { kind: TxKind::Stake, ..sample_tx(TX_VERSION) };
+        assert_ne!(transfer.hash(), stake.hash());
+        assert_eq!(Transaction::decode(&stake.encode()).unwrap().kind, TxKind::Stake);
+        // Version 1 can't carry a kind, so its signature wouldn't cover one
+        assert!(!Transaction { kind: TxKind::Stake, ..sample_tx(1) }.has_valid_layout());
+        assert!(!sample_tx(TX_VERSION + 1).has_valid_layout());
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let mut bytes = sample_tx(TX_VERSION).encode();
+        bytes[0] = TX_VERSION + 1;
+        assert_eq!(Transaction::decode(&bytes).err(), Some(DecodeError::UnsupportedVersion(TX_VERSION + 1)));
+
+        let mut bytes = sample_tx(TX_VERSION).encode();
+        bytes.push(0);
+        assert_eq!(Transaction::decode(&bytes).err(), Some(DecodeError::TrailingBytes));
+
+        let bytes = sample_tx(TX_VERSION).encode();
+        assert_eq!(Transaction::decode(&bytes[..bytes.len() - 1]).err(), Some(DecodeError::UnexpectedEnd));
+    }
+}
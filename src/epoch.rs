@@ -0,0 +1,100 @@
+use std::collections::{BTreeMap, BTreeSet};
+use serde::{Deserialize, Serialize};
+use crate::amount::Amount;
+use crate::storage::StateDelta;
+use crate::Blockchain;
+
+/// The validators leading slots for one epoch and their weights.
+///
+/// Each epoch's set is snapshotted from the state after the last block of the
+/// epoch before, so stake, delegation and slashing changes only move leader
+/// selection at the next epoch boundary and every node schedules from the same
+/// weights. Jailing is the exception: a jailed validator sits out at once.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ValidatorSet {
+    pub epoch: u64,
+    pub start_height: u64,
+    pub weights: BTreeMap<String, Amount>,
+    // The part of each weight delegated to the validator, which fees are shared out by
+    #[serde(default)]
+    pub delegated: BTreeMap<String, Amount>,
+}
+
+/// The active validator set and the one the next epoch will start with if
+/// nothing changes before its boundary, as served by `/epoch`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EpochInfo {
+    pub height: u64,  // Height of the next block
+    pub current: ValidatorSet,
+    pub next: ValidatorSet,
+}
+
+impl Blockchain {
+    // Validator set for the epoch starting at `start_height`, from the state with `delta` applied
+    pub(crate) fn snapshot_validators(&self, delta: &StateDelta, start_height: u64) -> ValidatorSet {
+        let candidates: BTreeSet<&String> = self.stakes.keys().chain(delta.stakes.keys()).collect();
+        let mut set = ValidatorSet { epoch: start_height / self.params.epoch_length, start_height, ..ValidatorSet::default() };
+        for addr in candidates {
+            let weight = self.staged_weight(delta, addr, start_height);
+            if weight == Amount::ZERO {
+                continue;
+            }
+            let delegated = self.staged_pool(delta, addr).tokens;
+            if delegated > Amount::ZERO {
+                set.delegated.insert(addr.clone(), delegated);
+            }
+            set.weights.insert(addr.clone(), weight);
+        }
+        set
+    }
+
+    // Weight a validator leads slots with this epoch
+    pub fn active_weight(&self, address: &str) -> Amount {
+        match self.is_jailed(address) {
+            true => Amount::ZERO,
+            false => self.validator_set.weights.get(address).copied().unwrap_or_default(),
+        }
+    }
+
+    pub fn epoch_info(&self) -> EpochInfo {
+        let next_start = (self.validator_set.epoch + 1) * self.params.epoch_length;
+        EpochInfo {
+            height: self.next_height(),
+            current: self.validator_set.clone(),
+            next: self.snapshot_validators(&StateDelta::default(), next_start),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::staking::TxKind;
+    use crate::testutil::{address, chain, extend, keypair, staking};
+
+    #[test]
+    fn stake_changes_wait_for_the_next_epoch() {
+        let validator = keypair(1);
+        let alice = keypair(2);
+        let mut bc = chain(&[(&validator, 1_000_000)], &[(&alice, 10_000_000)]);
+        bc.params.epoch_length = 3;
+        let units = Amount::from_base_units;
+
+        assert!(extend(&mut bc, &validator, vec![staking(&alice, TxKind::Stake, 5_000_000, 0)]));
+        let info = bc.epoch_info();
+        assert_eq!(info.current.weights.len(), 1);
+        assert_eq!(bc.active_weight(&address(&alice)), Amount::ZERO);
+        assert_eq!(info.next.start_height, 3);
+        assert_eq!(info.next.weights.get(&address(&alice)), Some(&units(5_000_000)));
+        let tip = bc.chain.back().unwrap().hash.clone();
+        assert!((0..100).all(|slot| bc.select_validator(&tip, slot) == Some(address(&validator))));
+
+        // Block 2 closes epoch 0, so block 3 is scheduled from the new set
+        assert!(extend(&mut bc, &validator, vec![staking(&alice, TxKind::Unstake, 1_000_000, 1)]));
+        let set = &bc.epoch_info().current;
+        assert_eq!((set.epoch, set.start_height), (1, 3));
+        assert_eq!(bc.active_weight(&address(&alice)), units(4_000_000));
+        assert_eq!(bc.active_weight(&address(&validator)), units(1_000_000));
+        assert!(bc.audit_chain().is_ok());
+    }
+}
@@ -0,0 +1,159 @@
+use ed25519_dalek::{PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
+use crate::address::Address;
+use crate::amount::Amount;
+use crate::chain_id::ChainId;
+use crate::encoding::{Versioned, TX_VERSION};
+use crate::staking::TxKind;
+use crate::{Blockchain, Transaction};
+
+// How many of the latest blocks the fee estimate looks at
+const RECENT_BLOCKS: usize = 20;
+
+/// Minimum fee a transaction must pay to enter this node's pool: a flat part plus
+/// a part per byte of its canonical encoding. Defaults to the genesis params; a
+/// node can raise it with `CACIA_MIN_FEE` and `CACIA_FEE_PER_BYTE`, in CC (e.g. `0.00005`).
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct FeePolicy {
+    pub flat: Amount,
+    pub per_byte: Amount,
+}
+
+impl FeePolicy {
+    pub fn with_env_overrides(self) -> Result<Self, String> {
+        let mut policy = self;
+        for (var, field) in [("CACIA_MIN_FEE", &mut policy.flat), ("CACIA_FEE_PER_BYTE", &mut policy.per_byte)] {
+            if let Ok(value) = std::env::var(var) {
+                *field = value.parse().map_err(|_| format!("invalid {} '{}'", var, value))?;
+            }
+        }
+        Ok(policy)
+    }
+
+    pub fn minimum(&self, size: usize) -> Amount {
+        self.per_byte
+            .checked_mul(size as u64)
+            .and_then(|variable| variable.checked_add(self.flat))
+            .unwrap_or(Amount::MAX)
+    }
+}
+
+// Size used for fee rates and the per-byte minimum
+pub fn tx_size(tx: &Transaction) -> usize {
+    tx.encode().len()
+}
+
+// Encoded size of an ordinary signed transfer; every field but the strings is fixed-width
+// and addresses, keys and signatures have fixed lengths, so this is the same for all of them
+fn standard_transfer_size(chain_id: ChainId) -> usize {
+    let public_key = PublicKey::from(&SecretKey::from_bytes(&[0; 32]).unwrap());
+    let address = Address::from_public_key(&public_key, chain_id).to_string();
+    tx_size(&Transaction {
+        version: TX_VERSION,
+        chain_id,
+        kind: TxKind::Transfer,
+        sender: address.clone(),
+        receiver: address,
+        amount: Amount::ZERO,
+        fee: Amount::ZERO,
+        nonce: 0,
+        signature: "0".repeat(128),
+        timestamp: 0,
+        public_key: hex::encode(public_key.as_bytes()),
+    })
+}
+
+// Fee per thousand bytes, so small rates don't round to zero
+fn rate(tx: &Transaction) -> u64 {
+    (tx.fee.base_units() as u128 * 1_000 / tx_size(tx) as u128) as u64
+}
+
+fn fee_at(rate: u64, size: usize) -> Amount {
+    Amount::from_base_units((rate as u128 * size as u128).div_ceil(1_000) as u64)
+}
+
+/// Suggested fees for a standard transfer.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FeeEstimate {
+    pub tx_size: usize,
+    pub minimum: Amount,  // The lowest fee this node accepts
+    pub normal: Amount,   // Median fee rate paid in recent blocks
+    pub fast: Amount,     // Enough to outbid the pool for a place in the next block
+}
+
+impl Blockchain {
+    pub fn estimate_fees(&self) -> FeeEstimate {
+        let size = standard_transfer_size(self.chain_id);
+        let minimum = self.fee_policy.minimum(size);
+
+        let mut recent: Vec<u64> = self
+            .chain
+            .iter()
+            .rev()
+            .take(RECENT_BLOCKS)
+            .flat_map(|block| block.transactions.iter().map(rate))
+            .collect();
+        recent.sort_unstable();
+        let normal = recent.get(recent.len() / 2).map(|r| fee_at(*r, size)).unwrap_or(Amount::ZERO).max(minimum);
+
+        // If more than a block's worth is queued, beat the last transaction that would make it in
+        let block_size = self.params.max_block_txs;
+        let queued = self.mempool.select(block_size + 1);
+        let fast = if queued.len() > block_size {
+            fee_at(rate(&queued[block_size - 1]), size).saturating_add(Amount::from_base_units(1))
+        } else {
+            Amount::ZERO
+        };
+
+        FeeEstimate { tx_size: size, minimum, normal, fast: fast.max(normal) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{chain, extend, keypair, transfer};
+
+    #[test]
+    fn minimum_is_flat_plus_per_byte() {
+        let units = Amount::from_base_units;
+        let policy = FeePolicy { flat: units(5_000), per_byte: units(10) };
+        assert_eq!(policy.minimum(0), units(5_000));
+        assert_eq!(policy.minimum(250), units(7_500));
+        let huge = FeePolicy { flat: units(1), per_byte: Amount::MAX };
+        assert_eq!(huge.minimum(2), Amount::MAX);
+    }
+
+    #[test]
+    fn estimates_follow_recent_fees_above_the_minimum() {
+        let validator = keypair(1);
+        let alice = keypair(2);
+        let mut bc = chain(&[(&validator, 1_000_000)], &[(&alice, 1_000_000)]);
+        let size = standard_transfer_size(bc.chain_id);
+        let empty = bc.estimate_fees();
+        assert_eq!(empty.tx_size, size);
+        assert_eq!(empty.minimum, bc.fee_policy.minimum(size));
+        assert_eq!((empty.normal, empty.fast), (empty.minimum, empty.minimum));
+
+        let paid: Vec<_> = (0..3).map(|nonce| transfer(&alice, &validator, 1, nonce)).collect();
+        assert!(extend(&mut bc, &validator, paid));
+        let estimate = bc.estimate_fees();
+        assert!(estimate.minimum < Amount::from_base_units(10_000));
+        assert_eq!(estimate.normal, Amount::from_base_units(10_000));
+        assert_eq!(estimate.fast, estimate.normal);
+    }
+
+    #[test]
+    fn blocks_paying_under_the_genesis_minimum_are_rejected() {
+        let validator = keypair(1);
+        let alice = keypair(2);
+        let mut bc = chain(&[(&validator, 1_000_000)], &[(&alice, 1_000_000)]);
+        // A node letting cheaper transactions into its own pool doesn't lower the floor for blocks
+        bc.fee_policy = FeePolicy { flat: Amount::ZERO, per_byte: Amount::ZERO };
+        let mut cheap = transfer(&alice, &validator, 1, 0);
+        cheap.fee = Amount::from_base_units(1);
+        cheap.sign(&alice);
+        assert!(!extend(&mut bc, &validator, vec![cheap]));
+        assert!(extend(&mut bc, &validator, vec![transfer(&alice, &validator, 1, 0)]));
+    }
+}
@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+use crate::storage::{StateDelta, StateDiff};
+use crate::{Block, Blockchain};
+
+// Side branches forking off further back than this are ignored
+pub const MAX_REORG_DEPTH: u64 = 100;
+
+/// Blocks we have seen that are not on the main chain, keyed by hash.
+///
+/// Together with the main chain this forms the block tree: every stored block's
+/// parent is either another stored block or a main-chain block.
+#[derive(Clone, Default)]
+pub struct BlockTree {
+    blocks: HashMap<String, Block>,
+}
+
+impl BlockTree {
+    pub fn insert(&mut self, block: Block) {
+        self.blocks.insert(block.hash.clone(), block);
+    }
+
+    pub fn remove(&mut self, hash: &str) {
+        self.blocks.remove(hash);
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.blocks.contains_key(hash)
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&Block> {
+        self.blocks.get(hash)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    // Walk back from `tip` through stored blocks. Returns the branch oldest-first and the
+    // hash of the first ancestor that isn't stored here (the fork point on the main chain).
+    pub fn branch(&self, tip: &str) -> (Vec<Block>, String) {
+        let mut branch = Vec::new();
+        let mut hash = tip.to_string();
+        while let Some(block) = self.blocks.get(&hash) {
+            hash = block.previous_hash.clone();
+            branch.push(block.clone());
+        }
+        branch.reverse();
+        (branch, hash)
+    }
+
+    // Drop blocks too far below the tip to ever be reorganised to
+    pub fn prune(&mut self, tip_index: u64) {
+        self.blocks.retain(|_, block| block.index + MAX_REORG_DEPTH > tip_index);
+    }
+}
+
+/// What recent blocks did to the state, for executing blocks on other branches.
+///
+/// Keeps the full state after one main-chain block at the bottom of the reorg
+/// window, the base, and the delta each later block applied, on the main chain
+/// and side branches alike. Recording a block costs only its own delta; the
+/// state after a recent block is rebuilt from the base along its branch when a
+/// block arrives on top of it.
+#[derive(Clone, Default)]
+pub struct StateHistory {
+    base_hash: String,
+    base_index: u64,
+    base: StateDelta,
+    deltas: HashMap<String, (u64, String, StateDelta)>,  // block hash -> height, parent hash, delta
+}
+
+impl StateHistory {
+    pub fn new(hash: String, index: u64, state: StateDelta) -> Self {
+        StateHistory { base_hash: hash, base_index: index, base: state, deltas: HashMap::new() }
+    }
+
+    pub fn record(&mut self, block: &Block, delta: StateDelta) {
+        self.deltas.insert(block.hash.clone(), (block.index, block.previous_hash.clone(), delta));
+    }
+
+    pub fn delta(&self, hash: &str) -> Option<&StateDelta> {
+        self.deltas.get(hash).map(|(_, _, delta)| delta)
+    }
+
+    pub fn take(&mut self, hash: &str) -> Option<StateDelta> {
+        self.deltas.remove(hash).map(|(_, _, delta)| delta)
+    }
+
+    // Blocks from just above the base up to `hash`, oldest first; None unless it descends from the base
+    fn path(&self, hash: &str) -> Option<Vec<String>> {
+        let mut path = Vec::new();
+        let mut hash = hash;
+        while hash != self.base_hash {
+            let (_, parent, _) = self.deltas.get(hash)?;
+            path.push(hash.to_string());
+            hash = parent;
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    // Full state after block `hash`, None unless it is recent enough to fork from
+    pub fn state_at(&self, hash: &str) -> Option<StateDelta> {
+        let path = self.path(hash)?;
+        let mut state = self.base.clone();
+        for hash in path {
+            state.merge(self.delta(&hash).unwrap());
+        }
+        Some(state)
+    }
+
+    // Move the base up to `hash`, a main-chain block, forgetting blocks no branch can fork from any more
+    pub fn advance_to(&mut self, hash: &str) {
+        let Some(path) = self.path(hash) else { return };
+        for hash in path {
+            let (index, _, delta) = self.deltas.remove(&hash).unwrap();
+            self.base.merge(&delta);
+            (self.base_hash, self.base_index) = (hash, index);
+        }
+        let floor = self.base_index;
+        self.deltas.retain(|_, (index, _, _)| *index > floor);
+    }
+}
+
+// Record for each key the value `values` holds for it, None where it holds none
+fn touch<V: Clone>(diff: &mut HashMap<String, Option<V>>, keys: impl Iterator<Item = String>, values: &HashMap<String, V>) {
+    for key in keys {
+        let value = values.get(&key).cloned();
+        diff.insert(key, value);
+    }
+}
+
+impl Blockchain {
+    fn main_chain_position(&self, hash: &str) -> Option<usize> {
+        self.chain.iter().rposition(|block| block.hash == hash)
+    }
+
+    fn is_known(&self, block: &Block) -> bool {
+        let on_main_chain = usize::try_from(block.index).ok().and_then(|i| self.chain.get(i)).is_some_and(|b| b.hash == block.hash);
+        on_main_chain || self.side_blocks.contains(&block.hash)
+    }
+
+    // Keep the history's base at the oldest block a branch may still fork from
+    pub(crate) fn advance_history(&mut self) {
+        if let Some(position) = self.chain.len().checked_sub(MAX_REORG_DEPTH as usize + 1) {
+            let hash = self.chain[position].hash.clone();
+            self.history.advance_to(&hash);
+        }
+    }
+
+    // The stored values a reorg onto `candidate` changes: every account the displaced and
+    // added `blocks` touched, as the candidate has it
+    pub(crate) fn reorg_diff(&self, blocks: &[Block], candidate: &Blockchain) -> Result<StateDiff, String> {
+        let mut diff = StateDiff {
+            validator_set: candidate.validator_set.clone(),
+            chain_weight: candidate.chain_weight,
+            burned: candidate.burned,
+            minted: candidate.minted,
+            ..StateDiff::default()
+        };
+        for block in blocks {
+            let delta = self.history.delta(&block.hash).ok_or_else(|| format!("no state history for block {}", block.hash))?;
+            touch(&mut diff.balances, delta.balances.keys().cloned(), &candidate.balances);
+            touch(&mut diff.stakes, delta.stakes.keys().cloned(), &candidate.stakes);
+            touch(&mut diff.nonces, delta.nonces.keys().cloned(), &candidate.nonces);
+            touch(&mut diff.unbonding, delta.unbonding.keys().cloned(), &candidate.unbonding);
+            touch(&mut diff.delegations, delta.delegations.keys().cloned(), &candidate.delegations);
+            touch(&mut diff.jailed, delta.jailed.keys().cloned(), &candidate.jailed);
+        }
+        Ok(diff)
+    }
+
+    // In-memory chain ending at `parent` with `state` as it stood after it, for executing
+    // blocks built on it without touching ours
+    pub(crate) fn replica_at(&self, parent: Block, state: StateDelta) -> Blockchain {
+        let mut replica = Blockchain::new(self.chain_id);
+        replica.params = self.params.clone();
+        replica.fee_policy = self.fee_policy;
+        replica.chain.push_back(parent);
+        replica.balances = state.balances;
+        replica.stakes = state.stakes;
+        replica.unbonding = state.unbonding;
+        replica.delegations = state.delegations;
+        replica.jailed = state.jailed;
+        replica.validator_set = state.validator_set.unwrap_or_default();
+        replica.nonces = state.nonces;
+        replica.chain_weight = state.chain_weight;
+        replica.burned = state.burned;
+        replica.minted = state.minted;
+        replica.genesis_state = self.genesis_state.clone();
+        replica
+    }
+
+    /// Import a block received from the network.
+    ///
+    /// Blocks extending the tip are applied directly. A block on another branch
+    /// is executed against its parent's state, which rejects it before anything
+    /// else runs unless its proposer led the slot there, and kept in the block
+    /// tree; if its branch ends up heavier than the main chain (more proposer
+    /// stake behind it) the node reorganises onto it. Returns true if the block
+    /// is now part of the main chain.
+    pub fn import_block(&mut self, block: Block) -> bool {
+        let tip = self.chain.back().unwrap();
+        if block.previous_hash == tip.hash {
+            return self.apply_block(block);
+        }
+        let tip_index = tip.index;
+
+        if self.is_known(&block) {
+            return false;
+        }
+        if !self.side_blocks.contains(&block.previous_hash) && self.main_chain_position(&block.previous_hash).is_none() {
+            println!("Ignoring block {}: unknown parent {}", block.index, block.previous_hash);
+            return false;
+        }
+        if !block.verify_signature(self.chain_id) {
+            println!("Rejected block {}: missing or invalid proposer signature", block.index);
+            return false;
+        }
+
+        let hash = block.hash.clone();
+        let (_, fork_hash) = self.side_blocks.branch(&block.previous_hash);
+        let fork_position = self.main_chain_position(&fork_hash).filter(|position| self.chain[*position].index + MAX_REORG_DEPTH >= tip_index);
+        let parent_state = fork_position.and_then(|_| self.history.state_at(&block.previous_hash));
+        let (Some(fork_position), Some(parent_state)) = (fork_position, parent_state) else {
+            println!("Ignoring block {}: forks off too deep", hash);
+            return false;
+        };
+        let parent = match self.side_blocks.get(&block.previous_hash) {
+            Some(parent) => parent.clone(),
+            None => self.chain[self.main_chain_position(&block.previous_hash).unwrap()].clone(),
+        };
+        let mut replica = self.replica_at(parent, parent_state);
+        if !replica.apply_block(block.clone()) {
+            println!("Rejected side-branch block {}", hash);
+            return false;
+        }
+        if let Some(delta) = replica.history.take(&hash) {
+            self.history.record(&block, delta);
+        }
+        self.side_blocks.insert(block);
+
+        let imported = match replica.chain_weight > self.chain_weight {
+            true => {
+                let (branch, _) = self.side_blocks.branch(&hash);
+                replica.chain = self.chain.iter().take(fork_position + 1).cloned().chain(branch).collect();
+                self.switch_to(replica)
+            }
+            false => {
+                println!("Stored side-branch block {}", hash);
+                false
+            }
+        };
+        let tip_index = self.chain.back().unwrap().index;
+        self.side_blocks.prune(tip_index);
+        imported
+    }
+
+    /// Import a chain sent by a peer block by block, skipping the ones we already
+    /// have, so it is held to the same rules and reorg depth as blocks arriving
+    /// one at a time. Returns true if our main chain changed.
+    pub fn import_chain(&mut self, chain: Vec<Block>) -> bool {
+        let tip = self.chain.back().unwrap().hash.clone();
+        for block in chain {
+            if self.is_known(&block) {
+                continue;
+            }
+            let hash = block.hash.clone();
+            // Nothing after a rejected block can be valid
+            if !self.import_block(block) && !self.side_blocks.contains(&hash) {
+                break;
+            }
+        }
+        self.chain.back().unwrap().hash != tip
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::testutil::{address, block_on, chain, extend, import, keypair, spec, transfer};
+
+    #[test]
+    fn reorgs_onto_longer_branch_and_requeues_orphans() {
+        let validator = keypair(1);
+        let (alice, bob, carol) = (keypair(2), keypair(3), keypair(4));
+        let mut bc = chain(&[(&validator, 100)], &[(&alice, 1_000_000), (&carol, 1_000_000)]);
+        let genesis = bc.chain[0].clone();
+
+        // Main chain: two blocks, the first paying bob from alice
+        let a1 = block_on(&bc, &genesis, genesis.slot, &validator, vec![transfer(&alice, &bob, 10, 0)]);
+        assert!(import(&mut bc, &a1));
+        let a2 = block_on(&bc, &a1, a1.slot, &validator, vec![]);
+        assert!(import(&mut bc, &a2));
+        assert_eq!(bc.get_balance(&address(&bob)), Amount::from_base_units(10));
+
+        // Competing branch from genesis with three blocks, paying bob from carol instead
+        let b1 = block_on(&bc, &genesis, a2.slot, &validator, vec![transfer(&carol, &bob, 7, 0)]);
+        assert!(!import(&mut bc, &b1));
+        let b2 = block_on(&bc, &b1, b1.slot, &validator, vec![]);
+        assert!(!import(&mut bc, &b2));
+        assert_eq!(bc.chain.back().unwrap().hash, a2.hash);
+
+        let b3 = block_on(&bc, &b2, b2.slot, &validator, vec![]);
+        assert!(import(&mut bc, &b3));
+        assert_eq!(bc.chain.back().unwrap().hash, b3.hash);
+        assert_eq!(bc.chain.len(), 4);
+        assert_eq!(bc.chain_weight, 300);
+
+        // State reflects only the new branch; alice's payment is back in the pool
+        assert_eq!(bc.get_balance(&address(&bob)), Amount::from_base_units(7));
+        assert_eq!(bc.get_balance(&address(&alice)), Amount::from_base_units(1_000_000));
+        assert_eq!(bc.mempool.len(), 1);
+        assert!(bc.mempool.get(&address(&alice), 0).is_some());
+
+        // The old branch is kept, so extending it past the new tip switches back
+        assert!(bc.side_blocks.contains(&a1.hash) && bc.side_blocks.contains(&a2.hash));
+        let a3 = block_on(&bc, &a2, b3.slot, &validator, vec![]);
+        assert!(!import(&mut bc, &a3));
+        let a4 = block_on(&bc, &a3, a3.slot, &validator, vec![]);
+        assert!(import(&mut bc, &a4));
+        assert_eq!(bc.chain.back().unwrap().hash, a4.hash);
+        assert_eq!(bc.get_balance(&address(&bob)), Amount::from_base_units(10));
+        assert_eq!(bc.mempool.len(), 1);
+        assert!(bc.mempool.get(&address(&carol), 0).is_some());
+        assert!(bc.audit_chain().is_ok());
+    }
+
+    #[test]
+    fn heavier_stake_beats_longer_chain() {
+        let (small, large) = (keypair(1), keypair(2));
+        let mut bc = chain(&[(&small, 100), (&large, 1_000)], &[]);
+        let genesis = bc.chain[0].clone();
+
+        let s1 = block_on(&bc, &genesis, genesis.slot, &small, vec![]);
+        assert!(import(&mut bc, &s1));
+        let s2 = block_on(&bc, &s1, s1.slot, &small, vec![]);
+        assert!(import(&mut bc, &s2));
+        assert_eq!(bc.chain_weight, 200);
+
+        // One block from the large validator outweighs two from the small one
+        let l1 = block_on(&bc, &genesis, s2.slot, &large, vec![]);
+        assert!(import(&mut bc, &l1));
+        assert_eq!(bc.chain.len(), 2);
+        assert_eq!(bc.chain.back().unwrap().hash, l1.hash);
+        assert_eq!(bc.chain_weight, 1_000);
+    }
+
+    #[test]
+    fn invalid_side_branch_is_discarded() {
+        let validator = keypair(1);
+        let (alice, bob) = (keypair(2), keypair(3));
+        let mut bc = chain(&[(&validator, 100)], &[(&alice, 1_000_000)]);
+        let genesis = bc.chain[0].clone();
+
+        let a1 = block_on(&bc, &genesis, genesis.slot, &validator, vec![]);
+        assert!(import(&mut bc, &a1));
+
+        // Signed by someone who isn't the scheduled leader
+        let mut b1 = block_on(&bc, &genesis, a1.slot, &validator, vec![transfer(&alice, &bob, 10, 0)]);
+        b1.validator = address(&bob);
+        b1.public_key = hex::encode(bob.public.as_bytes());
+        b1.hash = Blockchain::hash_block(&b1);
+        b1.sign(&bob);
+        let b2 = block_on(&bc, &b1, b1.slot, &validator, vec![]);
+        assert!(!import(&mut bc, &b1));
+        assert!(!bc.side_blocks.contains(&b1.hash));
+        assert!(!import(&mut bc, &b2));
+        assert_eq!(bc.chain.back().unwrap().hash, a1.hash);
+    }
+
+    #[test]
+    fn side_blocks_must_sit_one_above_their_parent() {
+        let validator = keypair(1);
+        let mut bc = chain(&[(&validator, 100)], &[]);
+        let genesis = bc.chain[0].clone();
+        let a1 = block_on(&bc, &genesis, genesis.slot, &validator, vec![]);
+        assert!(import(&mut bc, &a1));
+
+        // A sibling of block 1 claiming height 50, validly signed
+        let mut b1 = block_on(&bc, &genesis, a1.slot, &validator, vec![]);
+        b1.index = 50;
+        b1.hash = Blockchain::hash_block(&b1);
+        b1.sign(&validator);
+        assert!(!import(&mut bc, &b1));
+        assert!(!bc.side_blocks.contains(&b1.hash));
+
+        let b2 = block_on(&bc, &b1, b1.slot, &validator, vec![]);
+        assert!(!import(&mut bc, &b2));
+        assert_eq!(bc.chain.back().unwrap().hash, a1.hash);
+        assert!(bc.validate_chain());
+    }
+
+    #[test]
+    fn peer_chains_sync_block_by_block() {
+        let validator = keypair(1);
+        let (alice, bob) = (keypair(2), keypair(3));
+        let spec = spec(&[(&validator, 100)], &[(&alice, 1_000_000)]);
+        let (mut ours, mut theirs) = (Blockchain::from_genesis(&spec), Blockchain::from_genesis(&spec));
+        assert!(extend(&mut ours, &validator, vec![]));
+
+        // Theirs forks off genesis and is heavier, so we reorganise onto it
+        assert!(extend(&mut theirs, &validator, vec![transfer(&alice, &bob, 10, 0)]));
+        assert!(extend(&mut theirs, &validator, vec![]));
+        assert!(ours.import_chain(theirs.get_chain()));
+        assert_eq!(ours.chain.back().unwrap().hash, theirs.chain.back().unwrap().hash);
+        assert_eq!(ours.get_balance(&address(&bob)), Amount::from_base_units(10));
+
+        // Nothing new the second time round
+        assert!(!ours.import_chain(theirs.get_chain()));
+        assert!(ours.audit_chain().is_ok());
+    }
+
+    #[test]
+    fn branches_forking_past_the_reorg_depth_are_ignored() {
+        let validator = keypair(1);
+        let (alice, bob) = (keypair(2), keypair(3));
+        let spec = spec(&[(&validator, 100)], &[(&alice, 1_000_000)]);
+        let (mut ours, mut theirs) = (Blockchain::from_genesis(&spec), Blockchain::from_genesis(&spec));
+        for _ in 0..MAX_REORG_DEPTH + 1 {
+            assert!(extend(&mut ours, &validator, vec![]));
+        }
+        assert!(extend(&mut theirs, &validator, vec![transfer(&alice, &bob, 10, 0)]));
+        for _ in 0..MAX_REORG_DEPTH + 1 {
+            assert!(extend(&mut theirs, &validator, vec![]));
+        }
+
+        // Heavier, but it forks off genesis, which is now too far back
+        let tip = ours.chain.back().unwrap().hash.clone();
+        assert!(!ours.import_chain(theirs.get_chain()));
+        assert_eq!(ours.chain.back().unwrap().hash, tip);
+        assert!(!ours.side_blocks.contains(&theirs.chain[1].hash));
+    }
+}
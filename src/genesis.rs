@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::address::Address;
+use crate::amount::Amount;
+use crate::chain_id::ChainId;
+use crate::encoding::{Encode, Encoder, GENESIS_DOMAIN, GENESIS_VERSION};
+use crate::params::ChainParams;
+use crate::rewards::RewardSchedule;
+use crate::{TOTAL_SUPPLY, TREASURY};
+
+// Set here when a network's genesis is published; nodes refuse any other spec for it
+const TESTNET_GENESIS_HASH: &str = "4c482a8b96dc59eb579d0277d509c56de7a9434be8dae55bf3f33ac94dd5143d";
+const MAINNET_GENESIS_HASH: Option<&str> = None;
+
+const TESTNET_GENESIS: &str = include_str!("../genesis/testnet.json");
+
+// File a generated devnet spec is kept in, inside the data directory
+const DEVNET_GENESIS_FILE: &str = "genesis.json";
+
+/// Everything that defines a network's starting point.
+///
+/// Allocations are opening balances and validators opening stakes; between
+/// them they must hold exactly the total supply. Whatever isn't handed out
+/// sits in the treasury account.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GenesisSpec {
+    pub chain_id: ChainId,
+    pub timestamp: i64,
+    pub allocations: BTreeMap<String, Amount>,
+    pub validators: BTreeMap<String, Amount>,
+    #[serde(default)]
+    pub params: ChainParams,
+}
+
+impl Encode for GenesisSpec {
+    fn encode_to(&self, enc: &mut Encoder) {
+        enc.u32(self.chain_id.id());
+        enc.i64(self.timestamp);
+        for accounts in [&self.allocations, &self.validators] {
+            enc.u32(accounts.len() as u32);
+            for (addr, amount) in accounts {
+                enc.str(addr);
+                enc.u64(amount.base_units());
+            }
+        }
+        self.params.encode_to(enc);
+    }
+}
+
+impl GenesisSpec {
+    // Spec handing `validators` and `allocations` out of the supply, with the rest in the treasury
+    pub fn with_treasury(
+        chain_id: ChainId,
+        timestamp: i64,
+        validators: BTreeMap<String, Amount>,
+        mut allocations: BTreeMap<String, Amount>,
+    ) -> Result<Self, String> {
+        let handed_out = Amount::checked_sum(validators.values().chain(allocations.values()).copied())
+            .ok_or("genesis allocations overflow")?;
+        let treasury = TOTAL_SUPPLY.checked_sub(handed_out).ok_or("genesis allocations exceed the total supply")?;
+        allocations.insert(TREASURY.to_string(), treasury);
+        Ok(GenesisSpec { chain_id, timestamp, allocations, validators, params: ChainParams::default() })
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let spec: GenesisSpec = serde_json::from_str(json).map_err(|e| format!("invalid genesis spec: {}", e))?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// Hash identifying the spec, over its canonical encoding rather than the JSON text.
+    pub fn hash(&self) -> String {
+        let mut enc = Encoder::default();
+        enc.u8(GENESIS_VERSION);
+        enc.bytes(GENESIS_DOMAIN);
+        self.encode_to(&mut enc);
+        hex::encode(Sha256::digest(enc.finish()))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        // The treasury holds coins but has no key, so it can't stake or propose
+        let accounts = self.allocations.keys().filter(|addr| *addr != TREASURY);
+        for addr in accounts.chain(self.validators.keys()) {
+            Address::parse(addr, self.chain_id).map_err(|e| format!("genesis account {}: {}", addr, e))?;
+        }
+        if self.validators.values().all(|stake| *stake == Amount::ZERO) {
+            return Err("genesis has no staked validators".into());
+        }
+        if self.params.block_time == 0 || self.params.max_block_txs == 0 || self.params.epoch_length == 0 {
+            return Err("genesis params must have a non-zero block time, block size and epoch length".into());
+        }
+        // Unstaked coins still lead slots until the epoch ends, so they must stay slashable that long
+        if self.params.unbonding_period < self.params.epoch_length {
+            return Err("genesis unbonding period can't be shorter than an epoch".into());
+        }
+        if let RewardSchedule::Decaying { halving_interval: 0, .. } = self.params.reward {
+            return Err("genesis reward schedule must have a non-zero halving interval".into());
+        }
+        if self.params.slashing.fraction_bps > 10_000 {
+            return Err("genesis slashing fraction can't be over 100%".into());
+        }
+        let total = Amount::checked_sum(self.allocations.values().chain(self.validators.values()).copied());
+        if total != Some(TOTAL_SUPPLY) {
+            return Err(format!("genesis allocations and stakes must add up to the total supply of {} CC", TOTAL_SUPPLY));
+        }
+        Ok(())
+    }
+
+    /// The genesis spec a node on `chain_id` starts from.
+    ///
+    /// `CACIA_GENESIS` names a spec file to use instead of the built-in one.
+    /// Testnet and mainnet specs must hash to the pinned value. A devnet without
+    /// a spec file gets a fresh one, saved in the data directory, with this
+    /// node's key as the only validator.
+    pub fn load(chain_id: ChainId, data_dir: &Path, validator: &str) -> Result<Self, String> {
+        let spec = match std::env::var("CACIA_GENESIS") {
+            Ok(path) => Self::read(Path::new(&path))?,
+            Err(_) => match chain_id {
+                ChainId::Mainnet => return Err("no mainnet genesis has been published yet".into()),
+                ChainId::Testnet => Self::from_json(TESTNET_GENESIS)?,
+                ChainId::Devnet => {
+                    let path = data_dir.join(DEVNET_GENESIS_FILE);
+                    if path.exists() {
+                        Self::read(&path)?
+                    } else {
+                        Self::create_devnet(&path, validator)?
+                    }
+                }
+            },
+        };
+
+        if spec.chain_id != chain_id {
+            return Err(format!("genesis spec is for {}, not {}", spec.chain_id, chain_id));
+        }
+        let pinned = match chain_id {
+            ChainId::Mainnet => Some(MAINNET_GENESIS_HASH.ok_or("no mainnet genesis has been pinned yet")?),
+            ChainId::Testnet => Some(TESTNET_GENESIS_HASH),
+            ChainId::Devnet => None,
+        };
+        if let Some(pinned) = pinned {
+            if spec.hash() != pinned {
+                return Err(format!("genesis spec hash {} does not match the pinned {} genesis", spec.hash(), chain_id));
+            }
+        }
+        Ok(spec)
+    }
+
+    fn read(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|e| format!("reading {}: {}", path.display(), e))?;
+        Self::from_json(&json)
+    }
+
+    // Local single-validator devnet: this node stakes 1000 CC and gets 1000 CC to spend
+    fn create_devnet(path: &Path, validator: &str) -> Result<Self, String> {
+        let stake = BTreeMap::from([(validator.to_string(), Amount::from_coins(1_000))]);
+        let balance = BTreeMap::from([(validator.to_string(), Amount::from_coins(1_000))]);
+        let spec = Self::with_treasury(ChainId::Devnet, chrono::Utc::now().timestamp(), stake, balance)?;
+        let json = serde_json::to_string_pretty(&spec).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| format!("writing {}: {}", path.display(), e))?;
+        println!("Created devnet genesis {} at {}", spec.hash(), path.display());
+        Ok(spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_testnet_matches_pin() {
+        let spec = GenesisSpec::from_json(TESTNET_GENESIS).unwrap();
+        assert_eq!(spec.chain_id, ChainId::Testnet);
+        assert_eq!(spec.hash(), TESTNET_GENESIS_HASH);
+    }
+
+    #[test]
+    fn rejects_specs_that_miss_the_supply() {
+        let mut spec = GenesisSpec::from_json(TESTNET_GENESIS).unwrap();
+        *spec.allocations.get_mut(TREASURY).unwrap() = Amount::ZERO;
+        let json = serde_json::to_string(&spec).unwrap();
+        assert!(GenesisSpec::from_json(&json).is_err());
+    }
+
+    #[test]
+    fn rejects_the_treasury_as_a_validator() {
+        let mut spec = GenesisSpec::from_json(TESTNET_GENESIS).unwrap();
+        let stake = Amount::from_base_units(1_000_000);
+        let treasury = spec.allocations.get_mut(TREASURY).unwrap();
+        *treasury = treasury.checked_sub(stake).unwrap();
+        spec.validators.insert(TREASURY.to_string(), stake);
+        let json = serde_json::to_string(&spec).unwrap();
+        assert!(GenesisSpec::from_json(&json).unwrap_err().contains(TREASURY));
+    }
+
+    #[test]
+    fn rejects_unbonding_shorter_than_an_epoch() {
+        let mut spec = GenesisSpec::from_json(TESTNET_GENESIS).unwrap();
+        spec.params.unbonding_period = spec.params.epoch_length - 1;
+        let json = serde_json::to_string(&spec).unwrap();
+        assert!(GenesisSpec::from_json(&json).is_err());
+    }
+
+    #[test]
+    fn hash_covers_params() {
+        let spec = GenesisSpec::from_json(TESTNET_GENESIS).unwrap();
+        let slower = GenesisSpec { params: ChainParams { block_time: 10, ..spec.params.clone() }, ..spec.clone() };
+        assert_ne!(spec.hash(), slower.hash());
+    }
+}
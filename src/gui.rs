@@ -1,26 +1,26 @@
-use druid::{AppLauncher, Widget, WindowDesc, Data, Lens, Env, widget::{Label, TextBox, Button, Flex}};
-use druid::widget::Label;
-use ed25519_dalek::{Keypair, PublicKey, SecretKey};
-use rand::rngs::OsRng;
+use druid::{AppLauncher, Widget, WidgetExt, WindowDesc, Data, Lens, Env, widget::{Label, TextBox, Button, Flex}};
+use cacia::generate_keypair;
 use std::fs::{self, File};
 use std::io::Write;
-use hex;
+use cacia::address::Address;
+use cacia::chain_id::ChainId;
 
 #[derive(Clone, Data, Lens)]
 struct AppState {
     wallet_name: String,
+    address: String,
     public_key: String,
     private_key: String,
 }
 
-fn create_wallet(wallet_name: String) -> (String, String) {
-    let mut rng = OsRng;
-    let keypair = Keypair::generate(&mut rng);
+fn create_wallet(wallet_name: String, chain_id: ChainId) -> (String, String, String) {
+    let keypair = generate_keypair();
     let public_key = keypair.public;
     let private_key = keypair.secret;
 
     let public_key_hex = hex::encode(public_key.as_bytes());
     let private_key_hex = hex::encode(private_key.to_bytes());
+    let address = Address::from_public_key(&public_key, chain_id).to_string();
 
     // Save to files
     let wallet_dir = "./wallets";
@@ -35,11 +35,14 @@ fn create_wallet(wallet_name: String) -> (String, String) {
     public_file.write_all(public_key_hex.as_bytes()).expect("Failed to write public key");
     private_file.write_all(private_key_hex.as_bytes()).expect("Failed to write private key");
 
-    (public_key_hex, private_key_hex)
+    (address, public_key_hex, private_key_hex)
 }
 
-fn build_ui() -> impl Widget<AppState> {
+fn build_ui(chain_id: ChainId) -> impl Widget<AppState> {
     let wallet_name_box = TextBox::new().lens(AppState::wallet_name);
+    let address_label = Label::new(|data: &AppState, _env: &Env| {
+        format!("Address: {}", data.address)
+    });
     let public_key_label = Label::new(|data: &AppState, _env: &Env| {
         format!("Public Key: {}", data.public_key)
     });
@@ -47,8 +50,9 @@ fn build_ui() -> impl Widget<AppState> {
         format!("Private Key: {}", data.private_key)
     });
 
-    let create_button = Button::new("Create Account").on_click(|_ctx, data: &mut AppState, _env| {
-        let (public_key, private_key) = create_wallet(data.wallet_name.clone());
+    let create_button = Button::new("Create Account").on_click(move |_ctx, data: &mut AppState, _env| {
+        let (address, public_key, private_key) = create_wallet(data.wallet_name.clone(), chain_id);
+        data.address = address;
         data.public_key = public_key;
         data.private_key = private_key;
     });
@@ -60,6 +64,8 @@ fn build_ui() -> impl Widget<AppState> {
         .with_spacer(8.0)
         .with_child(create_button)
         .with_spacer(8.0)
+        .with_child(address_label)
+        .with_spacer(8.0)
         .with_child(public_key_label)
         .with_spacer(8.0)
         .with_child(private_key_label);
@@ -67,13 +73,15 @@ fn build_ui() -> impl Widget<AppState> {
     layout
 }
 
-pub fn run_gui() {
-    let main_window = WindowDesc::new(build_ui)
+// New wallets get addresses for the network the wallet was started against
+pub fn run_gui(chain_id: ChainId) {
+    let main_window = WindowDesc::new(move || build_ui(chain_id))
         .title("Cacia Cryptocurrency Wallet")
         .window_size((400.0, 200.0));
 
     let initial_state = AppState {
         wallet_name: String::new(),
+        address: String::new(),
         public_key: String::new(),
         private_key: String::new(),
     };
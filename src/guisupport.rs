@@ -1,75 +1,122 @@
-use clap::{App, Arg, SubCommand};
-use tokio;
-use cacia_gui::run_gui;  // Import the GUI function
+use clap::{Arg, Command};
+use cacia::address::Address;
+use cacia::amount::Amount;
+use cacia::chain_id::ChainId;
+use cacia::wallet;
+
+mod gui;
+use gui::run_gui;
 
 #[tokio::main]
 async fn main() {
-    let matches = App::new("Cacia (CC) CLI and GUI")
+    let matches = Command::new("Cacia (CC) CLI and GUI")
         .version("0.1.0")
         .author("Zone-crypto-ZNE")
         .about("Cacia cryptocurrency command-line and GUI tool")
+        .arg(Arg::new("chain")
+            .help("Network the addresses belong to (mainnet, testnet or devnet)")
+            .long("chain")
+            .default_value("devnet")
+            .global(true))
+        .arg(Arg::new("node")
+            .help("HTTP API of the node to talk to (defaults to the network's API address, or CACIA_API_ADDR)")
+            .long("node")
+            .global(true))
         .subcommand(
-            SubCommand::with_name("balance")
+            Command::new("balance")
                 .about("Check the balance of a Cacia wallet")
-                .arg(Arg::with_name("wallet")
-                    .help("The wallet address or username")
+                .arg(Arg::new("wallet")
+                    .help("The wallet address")
                     .required(true)
                     .index(1)),
         )
         .subcommand(
-            SubCommand::with_name("send")
+            Command::new("send")
                 .about("Send Cacia to another wallet")
-                .arg(Arg::with_name("from")
-                    .help("The sending wallet address")
+                .arg(Arg::new("from")
+                    .help("Name of the sending wallet, whose key in ./wallets signs the transaction")
                     .required(true)
                     .index(1))
-                .arg(Arg::with_name("to")
+                .arg(Arg::new("to")
                     .help("The receiving wallet address")
                     .required(true)
                     .index(2))
-                .arg(Arg::with_name("amount")
-                    .help("Amount of Cacia to send")
+                .arg(Arg::new("amount")
+                    .help("Amount of Cacia to send, in CC with up to 8 decimal places")
                     .required(true)
                     .index(3)),
         )
         .subcommand(
-            SubCommand::with_name("gui")
+            Command::new("gui")
                 .about("Launch the Cacia GUI"),
         )
         .get_matches();
 
+    let chain_id: ChainId = match matches.get_one::<String>("chain").unwrap().parse() {
+        Ok(chain_id) => chain_id,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+    let api = match wallet::node_api(matches.get_one::<String>("node"), chain_id) {
+        Ok(api) => api,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+
     match matches.subcommand() {
-        ("balance", Some(sub_matches)) => {
-            let wallet = sub_matches.value_of("wallet").unwrap();
-            check_balance(wallet).await;
+        Some(("balance", sub_matches)) => {
+            let wallet = sub_matches.get_one::<String>("wallet").unwrap();
+            check_balance(&api, wallet, chain_id).await;
         }
-        ("send", Some(sub_matches)) => {
-            let from_wallet = sub_matches.value_of("from").unwrap();
-            let to_wallet = sub_matches.value_of("to").unwrap();
-            let amount = sub_matches.value_of("amount").unwrap();
-            send_transaction(from_wallet, to_wallet, amount).await;
+        Some(("send", sub_matches)) => {
+            let from_wallet = sub_matches.get_one::<String>("from").unwrap();
+            let to_wallet = sub_matches.get_one::<String>("to").unwrap();
+            let amount = sub_matches.get_one::<String>("amount").unwrap();
+            send_transaction(&api, from_wallet, to_wallet, amount, chain_id).await;
         }
-        ("gui", _) => {
-            run_gui(); // Launch the GUI when "gui" command is used
+        Some(("gui", _)) => {
+            run_gui(chain_id); // Launch the GUI when "gui" command is used
         }
         _ => eprintln!("Invalid subcommand."),
     }
 }
 
-async fn check_balance(wallet: &str) {
-    let balance = get_balance_from_file(wallet).await;
-    println!("Balance for wallet {}: {} CC", wallet, balance);
-}
-
-async fn send_transaction(from: &str, to: &str, amount: &str) {
-    println!("Sending {} CC from {} to {}", amount, from, to);
+async fn check_balance(api: &str, wallet: &str, chain_id: ChainId) {
+    let address = match Address::parse(wallet, chain_id) {
+        Ok(address) => address,
+        Err(err) => {
+            eprintln!("Invalid wallet address {}: {}", wallet, err);
+            return;
+        }
+    };
+    match wallet::fetch_balance(api, &address).await {
+        Ok(balance) => println!("Balance for wallet {}: {} CC", address, balance),
+        Err(err) => eprintln!("Error checking balance: {}", err),
+    }
 }
 
-async fn get_balance_from_file(wallet: &str) -> u64 {
-    let path = format!("./wallets/{}.balance", wallet);
-    if let Ok(contents) = std::fs::read_to_string(path) {
-        contents.trim().parse().unwrap_or(0)
-    } else {
-        0
+async fn send_transaction(api: &str, from_wallet: &str, to: &str, amount: &str, chain_id: ChainId) {
+    let amount: Amount = match amount.parse() {
+        Ok(amount) => amount,
+        Err(err) => {
+            eprintln!("Invalid amount {}: {}", amount, err);
+            return;
+        }
+    };
+    let keypair = match wallet::load_keypair(from_wallet) {
+        Ok(keypair) => keypair,
+        Err(err) => {
+            eprintln!("Error loading wallet {}: {}", from_wallet, err);
+            return;
+        }
+    };
+    println!("Sending {} CC from {} to {}", amount, Address::from_public_key(&keypair.public, chain_id), to);
+    match wallet::send(api, &keypair, chain_id, to, amount).await {
+        Ok(tx) => println!("Transaction sent with fee {} CC and nonce {}: {}", tx.fee, tx.nonce, hex::encode(tx.hash())),
+        Err(err) => eprintln!("Error sending transaction: {}", err),
     }
 }
@@ -0,0 +1,29 @@
+// Launch tool: starts a node for the network named in LAUNCH_MODE, mainnet unless set
+
+use cacia::{NetworkProfile, Node};
+
+#[tokio::main]
+async fn main() {
+    let launch_mode = std::env::var("LAUNCH_MODE").unwrap_or_else(|_| "mainnet".to_string());
+
+    // Pick up the network's genesis, ports, seed peers and parameters from its profile
+    let profile = match NetworkProfile::new(&launch_mode).and_then(NetworkProfile::with_env_overrides) {
+        Ok(profile) => profile,
+        Err(e) => {
+            eprintln!("Invalid launch mode: {}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("Launching Cacia node in {} mode...", profile.name().to_uppercase());
+
+    let node = match Node::new(profile) {
+        Ok(node) => node,
+        Err(e) => {
+            eprintln!("Failed to start node: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Start the node; runs until the API server stops
+    node.start().await;
+}
@@ -0,0 +1,821 @@
+//! Cacia (CC) ledger: transactions, blocks, chain state and storage, plus the
+//! P2P network and [`Node`] the `node`, `cli`, `gui` and `lat` binaries build on.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use sha2::{Sha256, Digest};
+use serde::{Serialize, Deserialize};
+use chrono::Utc;
+use ed25519_dalek::{PublicKey, SecretKey, Signature, Signer, Verifier, Keypair};
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+
+pub mod address;
+pub mod amount;
+pub mod api;
+pub mod audit;
+pub mod chain_id;
+pub mod delegation;
+pub mod encoding;
+pub mod epoch;
+pub mod fees;
+mod forkchoice;
+pub mod genesis;
+pub mod mempool;
+pub mod network;
+pub mod node;
+pub mod params;
+mod producer;
+pub mod profile;
+pub mod rewards;
+pub mod slashing;
+pub mod staking;
+pub mod storage;
+pub mod tx_error;
+pub mod wallet;
+#[cfg(test)]
+mod testutil;
+use address::Address;
+use amount::Amount;
+use chain_id::ChainId;
+use encoding::{BLOCK_VERSION, GENESIS_VERSION};
+use delegation::DelegationPool;
+use epoch::ValidatorSet;
+use fees::FeePolicy;
+use forkchoice::{BlockTree, StateHistory};
+use genesis::GenesisSpec;
+use mempool::Mempool;
+use params::ChainParams;
+use slashing::{Evidence, Jail};
+use staking::{TxKind, Unbond};
+use storage::{StateDelta, Storage, StorageResult};
+use tx_error::TxError;
+
+pub use node::Node;
+pub use profile::NetworkProfile;
+
+pub const TOTAL_SUPPLY: Amount = Amount::from_coins(1_000_000_000);
+pub const TREASURY: &str = "treasury";  // Holds whatever the genesis spec does not allocate
+const FEE: Amount = Amount::from_base_units(5_000);  // Default minimum fee, plus FEE_PER_BYTE for each encoded byte
+const FEE_PER_BYTE: Amount = Amount::from_base_units(10);
+const BLOCK_TIME: u64 = 5;  // Defaults for genesis params
+const MAX_BLOCK_TXS: usize = 1_000;
+const UNBONDING_PERIOD: u64 = 100;  // Blocks before unstaked funds can be withdrawn
+const SLASH_FRACTION_BPS: u32 = 500;  // 5% of an equivocating validator's stake is burned
+const JAIL_PERIOD: u64 = 1_000;       // Blocks a slashed validator sits out
+const EPOCH_LENGTH: u64 = 100;        // Blocks per validator set
+
+// Fresh ed25519 keypair from OS randomness; dalek 1.0 wants an older rand_core than
+// rand 0.8 provides, so the secret is built from seed bytes rather than Keypair::generate
+pub fn generate_keypair() -> Keypair {
+    let mut seed = [0u8; 32];
+    OsRng.fill_bytes(&mut seed);
+    let secret = SecretKey::from_bytes(&seed).expect("32 bytes is a valid secret key");
+    let public = PublicKey::from(&secret);
+    Keypair { secret, public }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Transaction {
+    #[serde(default = "encoding::unversioned")]
+    pub version: u8,  // Layout it is encoded and signed in
+    pub chain_id: ChainId,  // Network this transaction is valid on; covered by the signature
+    #[serde(default)]
+    pub kind: TxKind,
+    pub sender: String,
+    pub receiver: String,
+    pub amount: Amount,
+    pub fee: Amount,
+    pub nonce: u64,  // Added for replay protection
+    pub signature: String,
+    pub timestamp: i64,
+    pub public_key: String,
+}
+
+impl Transaction {
+    pub fn hash(&self) -> Vec<u8> {
+        Sha256::digest(self.signing_bytes()).to_vec()
+    }
+
+    pub fn sign(&mut self, keypair: &Keypair) {
+        self.signature = hex::encode(keypair.sign(&self.hash()).to_bytes());
+    }
+
+    fn signer(&self) -> Option<PublicKey> {
+        hex::decode(&self.public_key).ok().and_then(|b| PublicKey::from_bytes(&b).ok())
+    }
+
+    // The sender must be the address derived from the signing key, otherwise a valid
+    // signature says nothing about who may spend the sender's balance
+    pub fn sender_matches_key(&self) -> bool {
+        match self.signer() {
+            Some(public_key) => Address::from_public_key(&public_key, self.chain_id).to_string() == self.sender,
+            None => false,
+        }
+    }
+
+    // Check the signature, and that it was made for the given network
+    pub fn verify_signature(&self, chain_id: ChainId) -> bool {
+        if self.chain_id != chain_id || !self.has_valid_layout() {
+            return false;
+        }
+        let public_key = match self.signer() {
+            Some(pk) => pk,
+            None => return false,
+        };
+        let sig_bytes = match hex::decode(&self.signature) {
+            Ok(b) => b,
+            Err(_) => return false,
+        };
+        let signature = match Signature::from_bytes(&sig_bytes) {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+        public_key.verify(&self.hash(), &signature).is_ok()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Block {
+    pub version: u8,  // Layout it is encoded and hashed in
+    pub index: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+    pub transactions: Vec<Transaction>,
+    pub previous_hash: String,
+    pub hash: String,
+    pub validator: String,
+    pub public_key: String,  // Proposer's ed25519 key; the validator address is derived from it
+    pub signature: String,   // Proposer's signature over the header hash
+}
+
+impl Block {
+    pub fn sign(&mut self, keypair: &Keypair) {
+        let hash_bytes = hex::decode(&self.hash).expect("block hash is hex");
+        self.signature = hex::encode(keypair.sign(&hash_bytes).to_bytes());
+    }
+
+    // Check the header hash, that the key belongs to the named validator and that it signed the hash
+    pub fn verify_signature(&self, chain_id: ChainId) -> bool {
+        if self.hash != Blockchain::hash_block(self) {
+            return false;
+        }
+        let public_key = match hex::decode(&self.public_key).ok().and_then(|b| PublicKey::from_bytes(&b).ok()) {
+            Some(pk) => pk,
+            None => return false,
+        };
+        if Address::from_public_key(&public_key, chain_id).to_string() != self.validator {
+            return false;
+        }
+        let signature = match hex::decode(&self.signature).ok().and_then(|b| Signature::from_bytes(&b).ok()) {
+            Some(sig) => sig,
+            None => return false,
+        };
+        let hash_bytes = match hex::decode(&self.hash) {
+            Ok(b) => b,
+            Err(_) => return false,
+        };
+        public_key.verify(&hash_bytes, &signature).is_ok()
+    }
+}
+
+#[derive(Clone)]
+pub struct Blockchain {
+    pub chain: VecDeque<Block>,
+    pub balances: HashMap<String, Amount>,
+    pub mempool: Mempool,
+    pub fee_policy: FeePolicy,  // Minimum fee for transactions entering the pool
+    pub stakes: HashMap<String, Amount>,
+    pub unbonding: HashMap<String, Vec<Unbond>>,  // Unstaked funds waiting out the unbonding period
+    pub delegations: HashMap<String, DelegationPool>,  // Stake delegated to each validator
+    pub jailed: HashMap<String, Jail>,  // Validators slashed for equivocating
+    pub validator_set: ValidatorSet,    // Leads the slots of the next block's epoch
+    pub nonces: HashMap<String, u64>,  // Confirmed next nonce per address; only apply_block advances it
+    pub chain_id: ChainId,
+    pub params: ChainParams,        // Protocol parameters from the genesis spec
+    genesis_state: StateDelta,  // Account state at height 0, the starting point for replaying chains
+    chain_weight: u128,         // Sum of proposer stakes over the main chain; the fork-choice score
+    pub burned: Amount,             // Value destroyed so far; part of the supply audit
+    pub minted: Amount,             // Value issued as block rewards on top of the genesis supply
+    side_blocks: BlockTree,     // Valid-looking blocks not on the main chain
+    history: StateHistory,      // What recent blocks did to the state, for executing blocks on other branches
+    pending_evidence: Vec<Evidence>,  // Double proposals seen but not yet reported
+    storage: Option<Storage>,
+}
+
+impl Blockchain {
+    // Empty in-memory chain; callers add genesis or load one from storage
+    pub fn new(chain_id: ChainId) -> Self {
+        Blockchain {
+            chain: VecDeque::new(),
+            balances: HashMap::new(),
+            mempool: Mempool::default(),
+            fee_policy: ChainParams::default().fee_policy(),
+            stakes: HashMap::new(),
+            unbonding: HashMap::new(),
+            delegations: HashMap::new(),
+            jailed: HashMap::new(),
+            validator_set: ValidatorSet::default(),
+            nonces: HashMap::new(),
+            chain_id,
+            params: ChainParams::default(),
+            genesis_state: StateDelta::default(),
+            chain_weight: 0,
+            burned: Amount::ZERO,
+            minted: Amount::ZERO,
+            side_blocks: BlockTree::default(),
+            history: StateHistory::default(),
+            pending_evidence: Vec::new(),
+            storage: None,
+        }
+    }
+
+    // Open the on-disk chain, creating and persisting genesis from the spec on first start
+    pub fn open<P: AsRef<Path>>(path: P, spec: &GenesisSpec) -> StorageResult<Self> {
+        let storage = Storage::open(path)?;
+        let mut bc = Blockchain::from_genesis(spec);
+
+        if storage.is_empty() {
+            storage.commit_genesis(&bc.chain[0], spec.chain_id, &bc.genesis_state)?;
+        } else {
+            if storage.chain_id()? != Some(spec.chain_id) {
+                return Err(format!("database does not belong to {}", spec.chain_id).into());
+            }
+            let genesis_hash = bc.chain[0].hash.clone();
+            bc.chain = storage.load_chain()?.into();
+            if bc.chain.front().map(|genesis| &genesis.hash) != Some(&genesis_hash) {
+                return Err("database was created from a different genesis spec".into());
+            }
+            bc.balances = storage.load_balances()?;
+            bc.stakes = storage.load_stakes()?;
+            bc.unbonding = storage.load_unbonding()?;
+            bc.delegations = storage.load_delegations()?;
+            bc.jailed = storage.load_jailed()?;
+            bc.validator_set = storage.load_validator_set()?;
+            bc.nonces = storage.load_nonces()?;
+            bc.genesis_state = storage.load_genesis_state()?;
+            bc.chain_weight = storage.load_chain_weight()?;
+            bc.burned = storage.load_burned()?;
+            bc.minted = storage.load_minted()?;
+            if !bc.validate_chain() {
+                return Err("stored chain failed validation".into());
+            }
+            // The replay also rebuilds the states needed to import blocks forking off recent ones
+            bc.history = bc.replay_audited()?.history;
+            println!("Recovered chain at height {}", bc.chain.back().unwrap().index);
+        }
+
+        bc.storage = Some(storage);
+        Ok(bc)
+    }
+
+    // In-memory chain holding the genesis block and state described by a spec
+    pub fn from_genesis(spec: &GenesisSpec) -> Blockchain {
+        let mut bc = Blockchain::new(spec.chain_id);
+        bc.params = spec.params.clone();
+        bc.fee_policy = spec.params.fee_policy();
+
+        // The genesis block commits to the spec through its previous hash, and is laid out
+        // in the spec's version so newer block layouts leave a network's genesis alone
+        let genesis = Block {
+            version: GENESIS_VERSION,
+            index: 0,
+            slot: spec.params.slot_at(spec.timestamp),
+            timestamp: spec.timestamp,
+            transactions: vec![],
+            previous_hash: spec.hash(),
+            hash: String::new(),
+            validator: "genesis_validator".to_string(),
+            public_key: String::new(),
+            signature: String::new(),
+        };
+        let hash = Self::hash_block(&genesis);
+        bc.chain.push_back(Block { hash: hash.clone(), ..genesis });
+        bc.balances = spec.allocations.clone().into_iter().collect();
+        bc.stakes = spec.validators.clone().into_iter().collect();
+        bc.validator_set = bc.snapshot_validators(&StateDelta::default(), 0);
+        bc.genesis_state = StateDelta {
+            balances: bc.balances.clone(),
+            stakes: bc.stakes.clone(),
+            validator_set: Some(bc.validator_set.clone()),
+            ..StateDelta::default()
+        };
+        bc.history = StateHistory::new(hash, 0, bc.genesis_state.clone());
+        bc
+    }
+
+    pub fn hash_block(block: &Block) -> String {
+        hex::encode(Sha256::digest(block.header_bytes()))
+    }
+
+    // Validate chain consistency (used for internal audits)
+    pub fn validate_chain(&self) -> bool {
+        // Genesis links to its spec hash rather than a block; open() checks it against the spec
+        let mut previous_hash = self.chain[0].previous_hash.clone();
+        for (height, block) in self.chain.iter().enumerate() {
+            if block.previous_hash != previous_hash || block.index != height as u64 {
+                return false;
+            }
+            let computed_hash = Self::hash_block(block);
+            if block.hash != computed_hash {
+                return false;
+            }
+            // Everything after genesis must be signed by its proposer
+            if block.index > 0 && !block.verify_signature(self.chain_id) {
+                return false;
+            }
+            previous_hash = block.hash.clone();
+        }
+        true
+    }
+
+    // In-memory chain holding only genesis and the genesis state, for replaying candidate chains
+    fn genesis_replica(&self) -> Blockchain {
+        let mut replica = self.replica_at(self.chain[0].clone(), self.genesis_state.clone());
+        replica.history = StateHistory::new(self.chain[0].hash.clone(), 0, self.genesis_state.clone());
+        replica
+    }
+
+    // Switch to a validated candidate chain, persisting it first. Our blocks past the fork
+    // point are kept as a side branch, their transactions that the new chain doesn't include
+    // go back into the pool, and pending transactions are re-checked against the new state.
+    fn switch_to(&mut self, candidate: Blockchain) -> bool {
+        let fork = self.chain.iter().zip(candidate.chain.iter()).take_while(|(a, b)| a.hash == b.hash).count();
+        let displaced: Vec<Block> = self.chain.iter().skip(fork).cloned().collect();
+        let included: HashSet<Vec<u8>> = candidate
+            .chain
+            .iter()
+            .skip(fork)
+            .flat_map(|block| block.transactions.iter().map(|tx| tx.hash()))
+            .collect();
+
+        if let Some(storage) = &self.storage {
+            let added: Vec<Block> = candidate.chain.iter().skip(fork).cloned().collect();
+            let persisted = self
+                .reorg_diff(&[displaced.as_slice(), added.as_slice()].concat(), &candidate)
+                .and_then(|diff| storage.reorganise(&displaced, &added, &diff).map_err(|e| e.to_string()));
+            if let Err(e) = persisted {
+                println!("Failed to persist reorg: {}", e);
+                return false;
+            }
+        }
+        let pending = self.mempool.drain();
+        self.chain = candidate.chain;
+        self.balances = candidate.balances;
+        self.stakes = candidate.stakes;
+        self.unbonding = candidate.unbonding;
+        self.delegations = candidate.delegations;
+        self.jailed = candidate.jailed;
+        self.validator_set = candidate.validator_set;
+        self.nonces = candidate.nonces;
+        self.chain_weight = candidate.chain_weight;
+        self.burned = candidate.burned;
+        self.minted = candidate.minted;
+        for block in self.chain.iter().skip(fork) {
+            self.side_blocks.remove(&block.hash);
+        }
+
+        let orphaned: Vec<Transaction> = displaced
+            .iter()
+            .flat_map(|block| block.transactions.iter())
+            .filter(|tx| !included.contains(&tx.hash()))
+            .cloned()
+            .collect();
+        if !displaced.is_empty() {
+            println!(
+                "Reorganised {} block(s) off the main chain, returning {} transaction(s) to the pool",
+                displaced.len(),
+                orphaned.len()
+            );
+        }
+        for block in displaced {
+            self.side_blocks.insert(block);
+        }
+        self.advance_history();
+        // Orphaned transactions carry earlier nonces than anything still pending
+        for tx in orphaned.into_iter().chain(pending) {
+            let hash = hex::encode(tx.hash());
+            if let Err(e) = self.add_transaction(tx) {
+                println!("Dropped transaction {} after reorg: {}", hash, e);
+            }
+        }
+        true
+    }
+
+    // Check a transaction against the current state and queue it in the pool
+    pub fn add_transaction(&mut self, tx: Transaction) -> Result<(), TxError> {
+        // Verify the transaction is for this network and signed, first
+        if tx.chain_id != self.chain_id {
+            return Err(TxError::WrongChain { expected: self.chain_id, found: tx.chain_id });
+        }
+        if !tx.verify_signature(self.chain_id) {
+            return Err(TxError::BadSignature);
+        }
+        if !tx.sender_matches_key() {
+            return Err(TxError::SenderKeyMismatch { sender: tx.sender });
+        }
+        if let Err(e) = Address::parse(&tx.receiver, self.chain_id) {
+            return Err(TxError::MalformedAddress { address: tx.receiver, reason: e.to_string() });
+        }
+        self.check_staking(&tx)?;
+
+        let minimum = self.fee_policy.minimum(fees::tx_size(&tx));
+        if tx.fee < minimum {
+            return Err(TxError::FeeTooLow { fee: tx.fee, minimum });
+        }
+
+        // Replay protection: the nonce must follow the sender's queued transactions,
+        // or replace one of them for a higher fee
+        let confirmed_nonce = self.confirmed_nonce(&tx.sender);
+        let expected_nonce = self.pending_nonce(&tx.sender);
+        if tx.nonce < confirmed_nonce || tx.nonce > expected_nonce {
+            return Err(TxError::WrongNonce { expected: expected_nonce, got: tx.nonce });
+        }
+
+        // The balance has to cover everything the sender has queued, including this
+        let replaced = match self.mempool.get(&tx.sender, tx.nonce) {
+            Some(old) => old.balance_debit().ok_or(TxError::AmountOverflow)?,
+            None => Amount::ZERO,
+        };
+        let spend = self
+            .mempool
+            .pending_spend(&tx.sender)
+            .checked_sub(replaced)
+            .zip(tx.balance_debit())
+            .and_then(|(spend, debit)| spend.checked_add(debit))
+            .ok_or(TxError::AmountOverflow)?;
+        let available = self.get_balance(&tx.sender);
+        if available < spend {
+            return Err(TxError::InsufficientBalance { required: spend, available });
+        }
+
+        self.mempool.insert(tx, Utc::now().timestamp())
+    }
+
+    // Drop pool transactions the latest state has made stale or unaffordable
+    fn revalidate_mempool(&mut self) {
+        let (nonces, balances) = (&self.nonces, &self.balances);
+        self.mempool.revalidate(
+            |sender| (nonces.get(sender).copied().unwrap_or(0), balances.get(sender).copied().unwrap_or_default()),
+            Utc::now().timestamp(),
+        );
+    }
+
+    // Leader for a slot weighted by the epoch's validator set, seeded from the parent block
+    // hash so every node computes the same schedule. Returns None when nobody has weight.
+    pub fn select_validator(&self, previous_hash: &str, slot: u64) -> Option<String> {
+        // The set is keyed by address, so stakers come out in a fixed order
+        let stakers: Vec<(&String, u64)> = self
+            .validator_set
+            .weights
+            .keys()
+            .map(|addr| (addr, self.active_weight(addr).base_units()))
+            .filter(|(_, weight)| *weight > 0)
+            .collect();
+        // Stakes come out of the fixed supply, so their total fits in a u64
+        let total_stake: u64 = stakers.iter().map(|(_, stake)| stake).sum();
+        if total_stake == 0 {
+            return None;
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(previous_hash.as_bytes());
+        hasher.update(slot.to_be_bytes());
+        let seed = hasher.finalize();
+        let pick = u64::from_be_bytes(seed[..8].try_into().unwrap()) % total_stake;
+
+        let mut cumulative = 0;
+        for (addr, stake) in stakers {
+            cumulative += stake;
+            if pick < cumulative {
+                return Some(addr.clone());
+            }
+        }
+        None
+    }
+
+    // Check that a block extending the tip was proposed by the scheduled leader for its slot
+    fn verify_proposer(&self, block: &Block) -> bool {
+        let parent = self.chain.back().unwrap();
+        if block.slot <= parent.slot || self.params.slot_at(block.timestamp) != block.slot {
+            println!("Rejected block {}: slot {} out of order", block.index, block.slot);
+            return false;
+        }
+        if block.slot > self.params.slot_at(Utc::now().timestamp()) + 1 {
+            println!("Rejected block {}: slot {} is in the future", block.index, block.slot);
+            return false;
+        }
+        match self.select_validator(&block.previous_hash, block.slot) {
+            Some(leader) if leader == block.validator => true,
+            leader => {
+                println!(
+                    "Rejected block {}: proposer {} is not the leader for slot {} ({:?})",
+                    block.index, block.validator, block.slot, leader
+                );
+                false
+            }
+        }
+    }
+
+    pub fn create_block(&mut self, slot: u64, keypair: &Keypair) -> Option<Block> {
+        self.mempool.expire(Utc::now().timestamp());
+        if self.mempool.is_empty() {
+            return None;
+        }
+        let previous_block = self.chain.back().unwrap();
+        let (index, previous_hash) = (previous_block.index + 1, previous_block.hash.clone());
+        let validator = Address::from_public_key(&keypair.public, self.chain_id).to_string();
+        if self.select_validator(&previous_hash, slot).as_ref() != Some(&validator) {
+            return None;
+        }
+
+        // Trial-run the selection so one transaction the state no longer allows can't
+        // invalidate the whole block; it and the sender's later ones leave the pool
+        let mut delta = StateDelta::default();
+        let mut txs = Vec::new();
+        for tx in self.mempool.select(self.params.max_block_txs) {
+            match self.execute_transaction(&mut delta, &tx, index) {
+                Ok(()) => txs.push(tx),
+                Err(e) => {
+                    println!("Leaving transaction {} out of block {}: {}", hex::encode(tx.hash()), index, e);
+                    self.mempool.remove_from(&tx.sender, tx.nonce);
+                }
+            }
+        }
+        if txs.is_empty() {
+            return None;
+        }
+
+        let block = Block {
+            version: BLOCK_VERSION,
+            index,
+            slot,
+            timestamp: Utc::now().timestamp(),
+            transactions: txs,
+            previous_hash,
+            hash: String::new(),
+            public_key: hex::encode(keypair.public.as_bytes()),
+            validator,
+            signature: String::new(),
+        };
+        let hash = Self::hash_block(&block);
+        let mut block = Block { hash, ..block };
+        block.sign(keypair);
+        Some(block)
+    }
+
+    // Execute a block against the touched accounts, persist the result, then update memory.
+    // Returns false (and changes nothing) if the block is not signed by the scheduled proposer,
+    // any of its transactions is invalid, or it could not be written to storage.
+    pub fn apply_block(&mut self, block: Block) -> bool {
+        // Heights key storage, unbonding, jail terms, rewards and epochs, so they must count up by one
+        let tip = self.chain.back().unwrap();
+        if block.previous_hash != tip.hash || block.index != tip.index + 1 {
+            println!("Rejected block {}: does not follow block {} at height {}", block.index, tip.hash, tip.index);
+            return false;
+        }
+        if !block.verify_signature(self.chain_id) {
+            println!("Rejected block {}: missing or invalid proposer signature", block.index);
+            return false;
+        }
+        if !self.verify_proposer(&block) {
+            return false;
+        }
+        if block.transactions.len() > self.params.max_block_txs {
+            println!("Rejected block {}: {} transactions exceeds the limit of {}", block.index, block.transactions.len(), self.params.max_block_txs);
+            return false;
+        }
+
+        // Each block adds its proposer's weight to the chain's fork-choice weight
+        let proposer_weight = self.active_weight(&block.validator);
+        let mut delta = StateDelta {
+            chain_weight: self.chain_weight + proposer_weight.base_units() as u128,
+            burned: self.burned,
+            minted: self.minted,
+            ..StateDelta::default()
+        };
+        // Every transaction runs against the staged state; one bad transaction rejects the block
+        for (position, tx) in block.transactions.iter().enumerate() {
+            if let Err(e) = self.execute_transaction(&mut delta, tx, block.index) {
+                println!("Rejected block {}: transaction {} is invalid: {}", block.index, position, e);
+                return false;
+            }
+        }
+        // The proposer earns the block's fees and its scheduled reward, shared with its delegators
+        let earned = self.issue_reward(&mut delta, block.index).and_then(|reward| {
+            let fees = Amount::checked_sum(block.transactions.iter().map(|tx| tx.fee)).ok_or(TxError::AmountOverflow)?;
+            let earned = fees.checked_add(reward).ok_or(TxError::AmountOverflow)?;
+            self.pay_proposer(&mut delta, &block.validator, earned)
+        });
+        if let Err(e) = earned {
+            println!("Rejected block {}: fees and reward could not be paid out: {}", block.index, e);
+            return false;
+        }
+        // The last block of an epoch fixes the validator set for the next one
+        if (block.index + 1).is_multiple_of(self.params.epoch_length) {
+            delta.validator_set = Some(self.snapshot_validators(&delta, block.index + 1));
+        }
+
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.commit_block(&block, &delta) {
+                println!("Failed to persist block {}: {}", block.index, e);
+                return false;
+            }
+        }
+        self.history.record(&block, delta.clone());
+        self.balances.extend(delta.balances);
+        self.stakes.extend(delta.stakes);
+        for (addr, unbonds) in delta.unbonding {
+            match unbonds.is_empty() {
+                true => self.unbonding.remove(&addr),
+                false => self.unbonding.insert(addr, unbonds),
+            };
+        }
+        for (validator, pool) in delta.delegations {
+            match pool.is_empty() {
+                true => self.delegations.remove(&validator),
+                false => self.delegations.insert(validator, pool),
+            };
+        }
+        self.jailed.extend(delta.jailed);
+        if let Some(set) = delta.validator_set {
+            println!("Epoch {} starts at height {} with {} validator(s)", set.epoch, set.start_height, set.weights.len());
+            self.validator_set = set;
+        }
+        self.nonces.extend(delta.nonces);
+        self.chain_weight = delta.chain_weight;
+        self.burned = delta.burned;
+        self.minted = delta.minted;
+        self.chain.push_back(block);
+        self.advance_history();
+        self.revalidate_mempool();
+        true
+    }
+
+    // Apply one transaction of the block at `height` to the staged delta, reading accounts it
+    // hasn't touched yet from committed state. Fees are left for apply_block to pay out once
+    // the whole block has run. Leaves the delta unchanged if the transaction is invalid.
+    fn execute_transaction(&self, delta: &mut StateDelta, tx: &Transaction, height: u64) -> Result<(), TxError> {
+        if tx.chain_id != self.chain_id {
+            return Err(TxError::WrongChain { expected: self.chain_id, found: tx.chain_id });
+        }
+        if !tx.verify_signature(self.chain_id) {
+            return Err(TxError::BadSignature);
+        }
+        if !tx.sender_matches_key() {
+            return Err(TxError::SenderKeyMismatch { sender: tx.sender.clone() });
+        }
+        if let Err(e) = Address::parse(&tx.receiver, self.chain_id) {
+            return Err(TxError::MalformedAddress { address: tx.receiver.clone(), reason: e.to_string() });
+        }
+        // The genesis floor, not this node's own pool policy, which may be set higher
+        let minimum = self.params.fee_policy().minimum(fees::tx_size(tx));
+        if tx.fee < minimum {
+            return Err(TxError::FeeTooLow { fee: tx.fee, minimum });
+        }
+
+        let expected = delta.nonces.get(&tx.sender).copied().unwrap_or_else(|| self.confirmed_nonce(&tx.sender));
+        if tx.nonce != expected {
+            return Err(TxError::WrongNonce { expected, got: tx.nonce });
+        }
+        match tx.kind {
+            TxKind::Transfer => {}
+            TxKind::Stake | TxKind::Unstake | TxKind::Withdraw => {
+                self.execute_staking(delta, tx, height)?;
+                delta.nonces.insert(tx.sender.clone(), tx.nonce + 1);
+                return Ok(());
+            }
+            TxKind::Delegate | TxKind::Undelegate | TxKind::SetCommission { .. } => {
+                self.execute_delegation(delta, tx, height)?;
+                delta.nonces.insert(tx.sender.clone(), tx.nonce + 1);
+                return Ok(());
+            }
+            TxKind::Evidence(_) => {
+                self.execute_evidence(delta, tx, height)?;
+                delta.nonces.insert(tx.sender.clone(), tx.nonce + 1);
+                return Ok(());
+            }
+        }
+        let available = self.staged_balance(delta, &tx.sender);
+        let required = tx.amount.checked_add(tx.fee).ok_or(TxError::AmountOverflow)?;
+        let remaining = available.checked_sub(required).ok_or(TxError::InsufficientBalance { required, available })?;
+
+        // The receiver may be the sender, so credit it from the sender's new balance then
+        let received = match tx.receiver == tx.sender {
+            true => remaining,
+            false => self.staged_balance(delta, &tx.receiver),
+        };
+        let received = received.checked_add(tx.amount).ok_or(TxError::AmountOverflow)?;
+        delta.balances.insert(tx.sender.clone(), remaining);
+        delta.balances.insert(tx.receiver.clone(), received);
+        delta.nonces.insert(tx.sender.clone(), tx.nonce + 1);
+        Ok(())
+    }
+
+    pub fn get_chain(&self) -> Vec<Block> {
+        self.chain.iter().cloned().collect()
+    }
+
+    pub fn get_balance(&self, address: &str) -> Amount {
+        self.balances.get(address).copied().unwrap_or_default()
+    }
+
+    // Nonce the account's next transaction in a block must carry
+    pub fn confirmed_nonce(&self, address: &str) -> u64 {
+        self.nonces.get(address).copied().unwrap_or(0)
+    }
+
+    // Nonce for a new transaction, following anything the account already has in the pool
+    pub fn pending_nonce(&self, address: &str) -> u64 {
+        self.mempool.next_nonce(address).unwrap_or_else(|| self.confirmed_nonce(address))
+    }
+
+    pub fn find_block(&self, hash: &str) -> Option<Block> {
+        match &self.storage {
+            Some(storage) => storage.block_by_hash(hash).unwrap_or_else(|e| {
+                println!("Block lookup failed: {}", e);
+                None
+            }),
+            None => self.chain.iter().find(|b| b.hash == hash).cloned(),
+        }
+    }
+
+    pub fn find_block_by_tx(&self, tx_hash: &str) -> Option<Block> {
+        match &self.storage {
+            Some(storage) => storage.block_by_tx(tx_hash).unwrap_or_else(|e| {
+                println!("Transaction lookup failed: {}", e);
+                None
+            }),
+            None => self
+                .chain
+                .iter()
+                .find(|b| b.transactions.iter().any(|tx| hex::encode(tx.hash()) == tx_hash))
+                .cloned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{address, block_on, chain, import, keypair, transfer};
+
+    // Signed by alice's key but claiming to spend bob's balance
+    fn spending_someone_else(alice: &Keypair, bob: &Keypair, carol: &Keypair) -> Transaction {
+        let mut tx = transfer(alice, carol, 1_000, 0);
+        tx.sender = address(bob);
+        tx.sign(alice);
+        assert!(tx.verify_signature(ChainId::Devnet));
+        tx
+    }
+
+    // A transfer alice signed for testnet, as a testnet wallet would make it
+    fn for_testnet(alice: &Keypair, bob: &Keypair) -> Transaction {
+        let mut tx = transfer(alice, bob, 1_000, 0);
+        tx.chain_id = ChainId::Testnet;
+        tx.sender = Address::from_public_key(&alice.public, ChainId::Testnet).to_string();
+        tx.receiver = Address::from_public_key(&bob.public, ChainId::Testnet).to_string();
+        tx.sign(alice);
+        assert!(tx.verify_signature(ChainId::Testnet));
+        tx
+    }
+
+    #[test]
+    fn transactions_for_another_chain_are_rejected() {
+        let (validator, alice, bob) = (keypair(1), keypair(2), keypair(3));
+        let mut bc = chain(&[(&validator, 1_000_000)], &[(&alice, 1_000_000)]);
+        let wrong_chain = Err(TxError::WrongChain { expected: ChainId::Devnet, found: ChainId::Testnet });
+        assert_eq!(bc.add_transaction(for_testnet(&alice, &bob)), wrong_chain);
+        assert_eq!(bc.execute_transaction(&mut StateDelta::default(), &for_testnet(&alice, &bob), 1), wrong_chain);
+
+        let tip = bc.chain.back().unwrap().clone();
+        let block = block_on(&bc, &tip, tip.slot, &validator, vec![for_testnet(&alice, &bob)]);
+        assert!(!import(&mut bc, &block));
+        assert_eq!(bc.chain.len(), 1);
+    }
+
+    #[test]
+    fn pool_rejects_a_sender_the_key_does_not_derive() {
+        let (validator, alice, bob, carol) = (keypair(1), keypair(2), keypair(3), keypair(4));
+        let mut bc = chain(&[(&validator, 1_000_000)], &[(&alice, 1_000_000), (&bob, 1_000_000)]);
+        let tx = spending_someone_else(&alice, &bob, &carol);
+        assert_eq!(bc.add_transaction(tx), Err(TxError::SenderKeyMismatch { sender: address(&bob) }));
+        assert_eq!(bc.mempool.len(), 0);
+    }
+
+    #[test]
+    fn blocks_reject_a_sender_the_key_does_not_derive() {
+        let (validator, alice, bob, carol) = (keypair(1), keypair(2), keypair(3), keypair(4));
+        let mut bc = chain(&[(&validator, 1_000_000)], &[(&alice, 1_000_000), (&bob, 1_000_000)]);
+        let tx = spending_someone_else(&alice, &bob, &carol);
+        let result = bc.execute_transaction(&mut StateDelta::default(), &tx, 1);
+        assert_eq!(result, Err(TxError::SenderKeyMismatch { sender: address(&bob) }));
+
+        let tip = bc.chain.back().unwrap().clone();
+        let block = block_on(&bc, &tip, tip.slot, &validator, vec![tx]);
+        assert!(!import(&mut bc, &block));
+        assert_eq!(bc.chain.len(), 1);
+        assert_eq!(bc.get_balance(&address(&bob)), Amount::from_base_units(1_000_000));
+    }
+}
@@ -0,0 +1,381 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use crate::amount::Amount;
+use crate::fees::tx_size;
+use crate::tx_error::TxError;
+use crate::Transaction;
+
+pub const MAX_POOL_TXS: usize = 10_000;
+pub const MAX_POOL_BYTES: usize = 8 * 1024 * 1024;
+pub const TX_TTL_SECS: i64 = 3 * 60 * 60;
+// A replacement for a pending nonce must raise the fee by at least this much
+pub const MIN_REPLACEMENT_BUMP_PERCENT: u64 = 10;
+
+#[derive(Clone)]
+struct Entry {
+    tx: Transaction,
+    size: usize,     // Canonical encoding length, the denominator of the fee rate
+    added_at: i64,
+}
+
+impl Entry {
+    // Fee per byte, compared by cross-multiplying so nothing is rounded away
+    fn cmp_fee_rate(&self, other: &Entry) -> Ordering {
+        let (fee, other_fee) = (self.tx.fee.base_units() as u128, other.tx.fee.base_units() as u128);
+        (fee * other.size as u128).cmp(&(other_fee * self.size as u128))
+    }
+}
+
+/// Transactions waiting to be included in a block.
+///
+/// Each sender has a queue keyed by nonce with no gaps, starting at their confirmed
+/// nonce, so every queued transaction is executable once its predecessors are.
+/// Blocks are filled by fee rate (fee per encoded byte) while keeping each sender's
+/// transactions in nonce order. When the pool is over its count or byte cap, the
+/// lowest fee-rate transaction at the end of a sender's queue is evicted, and
+/// transactions older than the TTL are dropped.
+#[derive(Clone)]
+pub struct Mempool {
+    queues: HashMap<String, BTreeMap<u64, Entry>>,
+    count: usize,
+    bytes: usize,
+    max_txs: usize,
+    max_bytes: usize,
+    ttl: i64,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Mempool::new(MAX_POOL_TXS, MAX_POOL_BYTES, TX_TTL_SECS)
+    }
+}
+
+impl Mempool {
+    pub fn new(max_txs: usize, max_bytes: usize, ttl: i64) -> Self {
+        Mempool { queues: HashMap::new(), count: 0, bytes: 0, max_txs, max_bytes, ttl }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn get(&self, sender: &str, nonce: u64) -> Option<&Transaction> {
+        self.queues.get(sender).and_then(|queue| queue.get(&nonce)).map(|entry| &entry.tx)
+    }
+
+    // Nonce following the sender's last queued transaction, if they have any
+    pub fn next_nonce(&self, sender: &str) -> Option<u64> {
+        self.queues.get(sender).and_then(|queue| queue.keys().next_back()).map(|nonce| nonce + 1)
+    }
+
+    // Total the sender's queued transactions will take out of their balance, fees included.
+    // Each was admitted against the sender's balance, so the sum fits.
+    pub fn pending_spend(&self, sender: &str) -> Amount {
+        self.queues
+            .get(sender)
+            .and_then(|queue| queue.values().try_fold(Amount::ZERO, |sum, entry| sum.checked_add(entry.tx.balance_debit()?)))
+            .unwrap_or(Amount::ZERO)
+    }
+
+    /// Add a transaction whose nonce the caller has checked is either the sender's
+    /// next one or one already queued (a replace-by-fee). May evict cheaper
+    /// transactions to stay within the caps.
+    pub fn insert(&mut self, tx: Transaction, now: i64) -> Result<(), TxError> {
+        let entry = Entry { size: tx_size(&tx), tx, added_at: now };
+        let sender = entry.tx.sender.clone();
+        let nonce = entry.tx.nonce;
+
+        if let Some(old) = self.queues.get(&sender).and_then(|queue| queue.get(&nonce)) {
+            if old.tx.hash() == entry.tx.hash() {
+                return Err(TxError::Duplicate);
+            }
+            let bump = old.tx.fee.base_units().saturating_mul(MIN_REPLACEMENT_BUMP_PERCENT).div_ceil(100).max(1);
+            let min_fee = old.tx.fee.saturating_add(Amount::from_base_units(bump));
+            if entry.tx.fee < min_fee {
+                return Err(TxError::ReplacementUnderpriced { old_fee: old.tx.fee, min_fee });
+            }
+        }
+
+        self.count += 1;
+        self.bytes += entry.size;
+        if let Some(old) = self.queues.entry(sender.clone()).or_default().insert(nonce, entry) {
+            self.count -= 1;
+            self.bytes -= old.size;
+            println!("Replaced pending tx {} from {} with a higher fee", nonce, sender);
+        }
+
+        while self.count > self.max_txs || self.bytes > self.max_bytes {
+            let (evicted_sender, evicted_nonce) = match self.cheapest_tail() {
+                Some(tail) => tail,
+                None => break,
+            };
+            self.remove_from(&evicted_sender, evicted_nonce);
+            if evicted_sender == sender && evicted_nonce == nonce {
+                return Err(TxError::PoolFull);
+            }
+            println!("Evicted tx {} from {}: pool full", evicted_nonce, evicted_sender);
+        }
+        Ok(())
+    }
+
+    // The lowest fee-rate transaction that can go without leaving a nonce gap behind
+    fn cheapest_tail(&self) -> Option<(String, u64)> {
+        self.queues
+            .iter()
+            .filter_map(|(sender, queue)| queue.iter().next_back().map(|(nonce, entry)| (sender, *nonce, entry)))
+            .min_by(|a, b| a.2.cmp_fee_rate(b.2).then(b.2.added_at.cmp(&a.2.added_at)))
+            .map(|(sender, nonce, _)| (sender.clone(), nonce))
+    }
+
+    // Remove a sender's transactions from `nonce` onwards
+    pub fn remove_from(&mut self, sender: &str, nonce: u64) {
+        let queue = match self.queues.get_mut(sender) {
+            Some(queue) => queue,
+            None => return,
+        };
+        for (_, entry) in queue.split_off(&nonce) {
+            self.count -= 1;
+            self.bytes -= entry.size;
+        }
+        if queue.is_empty() {
+            self.queues.remove(sender);
+        }
+    }
+
+    /// Pick up to `max_txs` transactions for a block, highest fee rate first, with each
+    /// sender's transactions in nonce order. Nothing is removed; included transactions
+    /// are dropped by `revalidate` once the block is applied.
+    pub fn select(&self, max_txs: usize) -> Vec<Transaction> {
+        struct Head<'a> {
+            entry: &'a Entry,
+            rest: std::collections::btree_map::Values<'a, u64, Entry>,
+        }
+        impl PartialEq for Head<'_> {
+            fn eq(&self, other: &Self) -> bool {
+                self.cmp(other) == Ordering::Equal
+            }
+        }
+        impl Eq for Head<'_> {}
+        impl PartialOrd for Head<'_> {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Head<'_> {
+            // Highest fee rate first, then oldest, then by sender so the order is total
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.entry
+                    .cmp_fee_rate(other.entry)
+                    .then(other.entry.added_at.cmp(&self.entry.added_at))
+                    .then(other.entry.tx.sender.cmp(&self.entry.tx.sender))
+            }
+        }
+
+        let mut heads: BinaryHeap<Head> = self
+            .queues
+            .values()
+            .filter_map(|queue| {
+                let mut rest = queue.values();
+                rest.next().map(|entry| Head { entry, rest })
+            })
+            .collect();
+        let mut selected = Vec::new();
+        while selected.len() < max_txs {
+            let Head { entry, mut rest } = match heads.pop() {
+                Some(head) => head,
+                None => break,
+            };
+            selected.push(entry.tx.clone());
+            if let Some(next) = rest.next() {
+                heads.push(Head { entry: next, rest });
+            }
+        }
+        selected
+    }
+
+    // Drop transactions past their TTL, along with anything queued behind them
+    pub fn expire(&mut self, now: i64) {
+        let expired: Vec<(String, u64)> = self
+            .queues
+            .iter()
+            .filter_map(|(sender, queue)| {
+                queue
+                    .iter()
+                    .find(|(_, entry)| now - entry.added_at > self.ttl)
+                    .map(|(nonce, _)| (sender.clone(), *nonce))
+            })
+            .collect();
+        for (sender, nonce) in expired {
+            println!("Expired pending tx {} from {}", nonce, sender);
+            self.remove_from(&sender, nonce);
+        }
+    }
+
+    /// Re-check every queue against the state after a block: drop transactions whose
+    /// nonce is now confirmed, queues that no longer start at the confirmed nonce, and
+    /// whatever the sender's balance can no longer cover. `account` returns a sender's
+    /// confirmed nonce and balance.
+    pub fn revalidate<F>(&mut self, account: F, now: i64)
+    where
+        F: Fn(&str) -> (u64, Amount),
+    {
+        self.expire(now);
+        let senders: Vec<String> = self.queues.keys().cloned().collect();
+        for sender in senders {
+            let (confirmed, balance) = account(&sender);
+            let queue = self.queues.get_mut(&sender).unwrap();
+            let pending = queue.split_off(&confirmed);
+            let included = std::mem::replace(queue, pending);
+            for entry in included.values() {
+                self.count -= 1;
+                self.bytes -= entry.size;
+            }
+
+            let mut first_invalid = None;
+            let mut spend = Some(Amount::ZERO);
+            for (offset, (nonce, entry)) in queue.iter().enumerate() {
+                spend = spend.zip(entry.tx.balance_debit()).and_then(|(s, debit)| s.checked_add(debit));
+                if *nonce != confirmed + offset as u64 || spend.is_none_or(|s| s > balance) {
+                    first_invalid = Some(*nonce);
+                    break;
+                }
+            }
+            match first_invalid {
+                Some(nonce) => {
+                    println!("Dropped pending txs from {} starting at nonce {}", sender, nonce);
+                    self.remove_from(&sender, nonce);
+                }
+                None if queue.is_empty() => {
+                    self.queues.remove(&sender);
+                }
+                None => {}
+            }
+        }
+    }
+
+    // Empty the pool, returning every transaction with each sender's in nonce order
+    pub fn drain(&mut self) -> Vec<Transaction> {
+        self.count = 0;
+        self.bytes = 0;
+        self.queues.drain().flat_map(|(_, queue)| queue.into_values().map(|entry| entry.tx)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::Keypair;
+    use crate::testutil::{address, keypair, transfer};
+
+    // A transfer with the given fee; every one has the same encoded size
+    fn paying(from: &Keypair, nonce: u64, fee: u64) -> Transaction {
+        let mut tx = transfer(from, &keypair(9), 1, nonce);
+        tx.fee = Amount::from_base_units(fee);
+        tx.sign(from);
+        tx
+    }
+
+    fn senders_and_nonces(txs: &[Transaction]) -> Vec<(String, u64)> {
+        txs.iter().map(|tx| (tx.sender.clone(), tx.nonce)).collect()
+    }
+
+    #[test]
+    fn replacements_must_raise_the_fee_by_the_minimum_bump() {
+        let alice = keypair(1);
+        let mut pool = Mempool::default();
+        pool.insert(paying(&alice, 0, 10_000), 0).unwrap();
+        assert_eq!(pool.insert(paying(&alice, 0, 10_000), 0), Err(TxError::Duplicate));
+        let underpriced = TxError::ReplacementUnderpriced {
+            old_fee: Amount::from_base_units(10_000),
+            min_fee: Amount::from_base_units(11_000),
+        };
+        assert_eq!(pool.insert(paying(&alice, 0, 10_999), 0), Err(underpriced));
+        pool.insert(paying(&alice, 0, 11_000), 0).unwrap();
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.get(&address(&alice), 0).unwrap().fee, Amount::from_base_units(11_000));
+    }
+
+    #[test]
+    fn full_pool_evicts_the_cheapest_tail() {
+        let (alice, bob, carol) = (keypair(1), keypair(2), keypair(3));
+        let mut pool = Mempool::new(2, MAX_POOL_BYTES, TX_TTL_SECS);
+        pool.insert(paying(&alice, 0, 1_000), 0).unwrap();
+        pool.insert(paying(&alice, 1, 50_000), 0).unwrap();
+
+        // Alice's first transaction pays least, but dropping it would strand the second
+        assert_eq!(pool.insert(paying(&bob, 0, 10_000), 0), Err(TxError::PoolFull));
+        assert_eq!(pool.len(), 2);
+        assert!(pool.get(&address(&bob), 0).is_none());
+
+        // The byte cap evicts the same way: room for two transfers, so the cheapest goes
+        let size = tx_size(&paying(&alice, 0, 1_000));
+        let mut pool = Mempool::new(MAX_POOL_TXS, 2 * size, TX_TTL_SECS);
+        pool.insert(paying(&alice, 0, 20_000), 0).unwrap();
+        pool.insert(paying(&bob, 0, 5_000), 0).unwrap();
+        pool.insert(paying(&carol, 0, 10_000), 0).unwrap();
+        assert_eq!(pool.len(), 2);
+        assert!(pool.get(&address(&bob), 0).is_none());
+        assert_eq!(pool.insert(paying(&bob, 0, 5_000), 0), Err(TxError::PoolFull));
+    }
+
+    #[test]
+    fn expiry_drops_everything_queued_behind_an_expired_transaction() {
+        let (alice, bob) = (keypair(1), keypair(2));
+        let mut pool = Mempool::new(MAX_POOL_TXS, MAX_POOL_BYTES, 100);
+        pool.insert(paying(&alice, 0, 10_000), 0).unwrap();
+        pool.insert(paying(&alice, 1, 10_000), 100).unwrap();
+        pool.insert(paying(&bob, 0, 10_000), 100).unwrap();
+        pool.expire(100);
+        assert_eq!(pool.len(), 3);
+        pool.expire(101);
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.next_nonce(&address(&alice)), None);
+        assert!(pool.get(&address(&bob), 0).is_some());
+    }
+
+    #[test]
+    fn revalidate_drops_included_gapped_and_unaffordable_transactions() {
+        let (alice, bob, carol) = (keypair(1), keypair(2), keypair(3));
+        let mut pool = Mempool::default();
+        for nonce in 0..3 {
+            pool.insert(paying(&alice, nonce, 10_000), 0).unwrap();
+        }
+        pool.insert(paying(&bob, 0, 10_000), 0).unwrap();
+        pool.insert(paying(&bob, 1, 10_000), 0).unwrap();
+        pool.insert(paying(&carol, 3, 10_000), 0).unwrap();
+
+        let (alice_addr, bob_addr) = (address(&alice), address(&bob));
+        pool.revalidate(
+            |sender| match sender {
+                s if s == alice_addr => (1, Amount::from_base_units(1_000_000)),
+                // Enough for one transfer of 1 with its fee, not two
+                s if s == bob_addr => (0, Amount::from_base_units(15_000)),
+                _ => (0, Amount::from_base_units(1_000_000)),
+            },
+            0,
+        );
+        assert_eq!(pool.select(10).len(), 3);
+        assert!(pool.get(&alice_addr, 0).is_none());
+        assert!(pool.get(&alice_addr, 1).is_some() && pool.get(&alice_addr, 2).is_some());
+        assert!(pool.get(&bob_addr, 0).is_some() && pool.get(&bob_addr, 1).is_none());
+        assert_eq!(pool.next_nonce(&address(&carol)), None);
+        assert_eq!(pool.len(), 3);
+    }
+
+    #[test]
+    fn select_orders_by_fee_rate_but_keeps_nonce_order() {
+        let (alice, bob) = (keypair(1), keypair(2));
+        let mut pool = Mempool::default();
+        pool.insert(paying(&alice, 0, 1_000), 0).unwrap();
+        pool.insert(paying(&alice, 1, 50_000), 0).unwrap();
+        pool.insert(paying(&bob, 0, 10_000), 0).unwrap();
+        let (a, b) = (address(&alice), address(&bob));
+        assert_eq!(senders_and_nonces(&pool.select(3)), vec![(b.clone(), 0), (a.clone(), 0), (a.clone(), 1)]);
+        assert_eq!(senders_and_nonces(&pool.select(1)), vec![(b, 0)]);
+        assert_eq!(pool.len(), 3);
+    }
+}
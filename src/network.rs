@@ -1,8 +1,80 @@
 use std::sync::{Arc, Mutex};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use serde_json;
 use crate::{Blockchain, Block, Transaction};
+use crate::encoding::{Decode, DecodeError, Decoder, Encode, Encoder, Versioned};
+
+// Upper bound on a single frame so a peer can't make us allocate arbitrarily much
+const MAX_FRAME_LEN: usize = 32 * 1024 * 1024;
+
+// Messages up to 2 carry blocks and transactions in the message's own layout;
+// 3 prefixes each with its own version
+const MESSAGE_VERSION: u8 = 3;
+
+// Wire messages; each is sent as a u32 big-endian length followed by its canonical encoding
+pub enum Message {
+    Chain(Vec<Block>),
+    Block(Block),
+    Transaction(Transaction),
+}
+
+impl Encode for Message {
+    fn encode_to(&self, enc: &mut Encoder) {
+        match self {
+            Message::Chain(chain) => {
+                enc.u8(1);
+                enc.versioned_seq(chain);
+            }
+            Message::Block(block) => {
+                enc.u8(2);
+                enc.versioned(block);
+            }
+            Message::Transaction(tx) => {
+                enc.u8(3);
+                enc.versioned(tx);
+            }
+        }
+    }
+}
+
+impl Decode for Message {
+    fn decode_from(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let legacy = dec.version() < 3;
+        match dec.u8()? {
+            1 if legacy => Ok(Message::Chain(dec.seq()?)),
+            1 => Ok(Message::Chain(dec.versioned_seq()?)),
+            2 if legacy => Ok(Message::Block(Block::decode_from(dec)?)),
+            2 => Ok(Message::Block(dec.versioned()?)),
+            3 if legacy => Ok(Message::Transaction(Transaction::decode_from(dec)?)),
+            3 => Ok(Message::Transaction(dec.versioned()?)),
+            tag => Err(DecodeError::UnknownTag(tag)),
+        }
+    }
+}
+
+impl Versioned for Message {
+    const LATEST: u8 = MESSAGE_VERSION;
+
+    fn version(&self) -> u8 {
+        MESSAGE_VERSION
+    }
+}
+
+async fn write_frame(stream: &mut TcpStream, msg: &Message) -> std::io::Result<()> {
+    let payload = msg.encode();
+    stream.write_u32(payload.len() as u32).await?;
+    stream.write_all(&payload).await
+}
+
+async fn read_frame(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let len = stream.read_u32().await? as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "frame too large"));
+    }
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).await?;
+    Ok(payload)
+}
 
 #[derive(Clone)]
 pub struct Network {
@@ -40,8 +112,7 @@ impl Network {
             Ok(mut stream) => {
                 println!("Connected to peer {}", peer);
                 let chain = self.bc.lock().unwrap().get_chain();
-                let msg = serde_json::to_string(&chain).unwrap();
-                if let Err(e) = stream.write_all(msg.as_bytes()).await {
+                if let Err(e) = write_frame(&mut stream, &Message::Chain(chain)).await {
                     println!("Error sending chain to {}: {}", peer, e);
                 }
             }
@@ -50,65 +121,54 @@ impl Network {
     }
 
     async fn handle_connection(mut stream: TcpStream, addr: std::net::SocketAddr, bc: Arc<Mutex<Blockchain>>) {
-        let mut buffer = [0u8; 4096];
-        match stream.read(&mut buffer).await {
-            Ok(n) if n > 0 => {
-                let msg = String::from_utf8_lossy(&buffer[..n]).to_string();
-                println!("Received {} bytes from {}", n, addr);
-
-                // Attempt to deserialize as a chain first
-                if let Ok(chain) = serde_json::from_str::<Vec<Block>>(&msg) {
-                    let mut bc_lock = bc.lock().unwrap();
-                    if chain.len() > bc_lock.chain.len() {
-                        println!("Syncing chain from peer {}", addr);
-                        // Assume incoming chain is valid; ideally, run validate_chain() here
-                        bc_lock.chain = chain.into_iter().collect();
+        match read_frame(&mut stream).await {
+            Ok(payload) => {
+                println!("Received {} bytes from {}", payload.len(), addr);
+
+                match Message::decode(&payload) {
+                    Ok(Message::Chain(chain)) => {
+                        let mut bc_lock = bc.lock().unwrap();
+                        // Imported block by block, so a peer's chain gets the same checks and
+                        // reorg depth limit as blocks gossiped one at a time
+                        if bc_lock.import_chain(chain) {
+                            println!("Synced chain from peer {}", addr);
+                        }
                     }
-                }
-                // Attempt to deserialize as a block
-                else if let Ok(block) = serde_json::from_str::<Block>(&msg) {
-                    let mut bc_lock = bc.lock().unwrap();
-                    if block.index == bc_lock.chain.back().unwrap().index + 1 &&
-                       block.previous_hash == bc_lock.chain.back().unwrap().hash {
-                        bc_lock.apply_block(block);
-                        println!("Applied block from peer {}", addr);
+                    Ok(Message::Block(block)) => {
+                        let mut bc_lock = bc.lock().unwrap();
+                        bc_lock.detect_equivocation(&block);
+                        if bc_lock.import_block(block) {
+                            println!("Applied block from peer {}", addr);
+                        }
                     }
-                }
-                // Attempt to deserialize as a transaction
-                else if let Ok(tx) = serde_json::from_str::<Transaction>(&msg) {
-                    let mut bc_lock = bc.lock().unwrap();
-                    if bc_lock.add_transaction(tx) {
-                        println!("Added transaction from peer {}", addr);
+                    Ok(Message::Transaction(tx)) => {
+                        let mut bc_lock = bc.lock().unwrap();
+                        match bc_lock.add_transaction(tx) {
+                            Ok(()) => println!("Added transaction from peer {}", addr),
+                            Err(e) => println!("Rejected transaction from peer {}: {}", addr, e),
+                        }
                     }
-                } else {
-                    println!("Unrecognized message format from peer {}", addr);
+                    Err(e) => println!("Unrecognized message from peer {}: {}", addr, e),
                 }
                 let _ = stream.write_all(b"ACK").await;
             }
-            Ok(_) => println!("Received empty message from {}", addr),
             Err(e) => println!("Error reading from {}: {}", addr, e),
         }
     }
 
     pub async fn broadcast_block(&self, block: Block) {
-        let msg = serde_json::to_string(&block).unwrap();
-        for peer in &self.peers {
-            if let Ok(mut stream) = TcpStream::connect(peer).await {
-                if let Err(e) = stream.write_all(msg.as_bytes()).await {
-                    println!("Error broadcasting block to {}: {}", peer, e);
-                }
-            } else {
-                println!("Could not connect to peer {}", peer);
-            }
-        }
+        self.broadcast(&Message::Block(block), "block").await;
     }
 
     pub async fn broadcast_tx(&self, tx: Transaction) {
-        let msg = serde_json::to_string(&tx).unwrap();
+        self.broadcast(&Message::Transaction(tx), "transaction").await;
+    }
+
+    async fn broadcast(&self, msg: &Message, what: &str) {
         for peer in &self.peers {
             if let Ok(mut stream) = TcpStream::connect(peer).await {
-                if let Err(e) = stream.write_all(msg.as_bytes()).await {
-                    println!("Error broadcasting transaction to {}: {}", peer, e);
+                if let Err(e) = write_frame(&mut stream, msg).await {
+                    println!("Error broadcasting {} to {}: {}", what, peer, e);
                 }
             } else {
                 println!("Could not connect to peer {}", peer);
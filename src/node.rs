@@ -0,0 +1,69 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+use crate::address::Address;
+use crate::network::Network;
+use crate::profile::NetworkProfile;
+use crate::storage::StorageResult;
+use crate::{api, generate_keypair, producer, Blockchain};
+
+const VALIDATOR_KEY_FILE: &str = "validator.key";
+
+/// A running Cacia node: the chain, the P2P server, block production and the
+/// REST API, set up for one network profile.
+pub struct Node {
+    pub profile: NetworkProfile,
+    pub bc: Arc<Mutex<Blockchain>>,
+    network: Network,
+    keypair: Keypair,
+}
+
+impl Node {
+    /// Open (or create) the profile's data directory, validator key, genesis and chain.
+    pub fn new(profile: NetworkProfile) -> StorageResult<Self> {
+        std::fs::create_dir_all(&profile.data_dir)?;
+        let keypair = load_or_create_keypair(&profile.data_dir.join(VALIDATOR_KEY_FILE))?;
+        let validator = Address::from_public_key(&keypair.public, profile.chain_id).to_string();
+
+        let spec = profile.genesis(&validator)?;
+        let fee_policy = spec.params.fee_policy().with_env_overrides()?;
+        let mut blockchain = Blockchain::open(&profile.data_dir, &spec)?;
+        blockchain.fee_policy = fee_policy;
+        let bc = Arc::new(Mutex::new(blockchain));
+
+        println!("Cacia (CC) {} node, genesis {}", profile.name(), spec.hash());
+        println!("Validator address: {}", validator);
+        let network = Network::new(bc.clone(), profile.p2p_addr.clone(), profile.seed_peers.clone());
+        Ok(Node { profile, bc, network, keypair })
+    }
+
+    /// Run the node until the API server stops.
+    pub async fn start(self) {
+        let Node { profile, bc, network, keypair } = self;
+        tokio::spawn(producer::run(bc.clone(), network.clone(), keypair));
+        tokio::spawn(async move {
+            if let Err(e) = network.run().await {
+                println!("P2P server stopped: {}", e);
+            }
+        });
+        println!("API listening on {}", profile.api_addr);
+        warp::serve(api::routes(bc)).run(profile.api_addr).await;
+    }
+}
+
+// Load this node's validator key, generating and saving one on first start
+fn load_or_create_keypair(path: &Path) -> StorageResult<Keypair> {
+    if !path.exists() {
+        let keypair = generate_keypair();
+        // Only the node's own user may read its validator key
+        let mut file = OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)?;
+        file.write_all(hex::encode(keypair.secret.to_bytes()).as_bytes())?;
+        return Ok(keypair);
+    }
+    let secret = SecretKey::from_bytes(&hex::decode(std::fs::read_to_string(path)?.trim())?)?;
+    let public = PublicKey::from(&secret);
+    Ok(Keypair { secret, public })
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+use crate::amount::Amount;
+use crate::encoding::{Encode, Encoder};
+use crate::fees::FeePolicy;
+use crate::rewards::RewardSchedule;
+use crate::slashing::SlashingParams;
+use crate::{BLOCK_TIME, EPOCH_LENGTH, FEE, FEE_PER_BYTE, MAX_BLOCK_TXS, UNBONDING_PERIOD};
+
+/// Protocol parameters fixed by a network's genesis spec.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChainParams {
+    pub block_time: u64,       // Slot length in seconds
+    pub max_block_txs: usize,
+    pub min_fee: Amount,       // Default minimum fee; nodes may raise it for their own pool
+    pub fee_per_byte: Amount,
+    pub unbonding_period: u64, // Blocks between unstaking and being able to withdraw
+    #[serde(default = "default_epoch_length")]
+    pub epoch_length: u64,     // Blocks per epoch; validator set changes wait for the next one
+    #[serde(default)]
+    pub reward: RewardSchedule, // Paid to each block's proposer on top of its fees
+    #[serde(default)]
+    pub slashing: SlashingParams,
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        ChainParams {
+            block_time: BLOCK_TIME,
+            max_block_txs: MAX_BLOCK_TXS,
+            min_fee: FEE,
+            fee_per_byte: FEE_PER_BYTE,
+            unbonding_period: UNBONDING_PERIOD,
+            epoch_length: EPOCH_LENGTH,
+            reward: RewardSchedule::None,
+            slashing: SlashingParams::default(),
+        }
+    }
+}
+
+fn default_epoch_length() -> u64 {
+    EPOCH_LENGTH
+}
+
+impl ChainParams {
+    // Slot number for a unix timestamp; slots are block_time seconds wide
+    pub fn slot_at(&self, timestamp: i64) -> u64 {
+        timestamp.max(0) as u64 / self.block_time
+    }
+
+    pub fn fee_policy(&self) -> FeePolicy {
+        FeePolicy { flat: self.min_fee, per_byte: self.fee_per_byte }
+    }
+}
+
+impl Encode for ChainParams {
+    fn encode_to(&self, enc: &mut Encoder) {
+        enc.u64(self.block_time);
+        enc.u64(self.max_block_txs as u64);
+        enc.u64(self.min_fee.base_units());
+        enc.u64(self.fee_per_byte.base_units());
+        enc.u64(self.unbonding_period);
+        enc.u64(self.epoch_length);
+        self.reward.encode_to(enc);
+        self.slashing.encode_to(enc);
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::{Arc, Mutex};
+use chrono::Utc;
+use ed25519_dalek::Keypair;
+use tokio::time::{sleep, Duration};
+use crate::address::Address;
+use crate::network::Network;
+use crate::Blockchain;
+
+/// Block production loop.
+///
+/// Wakes at every slot boundary and, when this node is the selected validator,
+/// builds a block from the pending transactions, signs it with the node's key,
+/// applies it locally and hands it to the network. Slots with nothing to
+/// include are skipped, and slots the loop slept through are logged as missed
+/// rather than produced late. Double proposals the network has spotted are
+/// reported as evidence transactions signed with the node's key.
+pub async fn run(bc: Arc<Mutex<Blockchain>>, network: Network, keypair: Keypair) {
+    let (chain_id, params) = {
+        let bc_locked = bc.lock().unwrap();
+        (bc_locked.chain_id, bc_locked.params.clone())
+    };
+    let validator = Address::from_public_key(&keypair.public, chain_id).to_string();
+    let mut last_slot = params.slot_at(Utc::now().timestamp());
+
+    loop {
+        let now = Utc::now().timestamp().max(0) as u64;
+        let next_slot_start = (now / params.block_time + 1) * params.block_time;
+        sleep(Duration::from_secs(next_slot_start - now)).await;
+
+        let slot = params.slot_at(Utc::now().timestamp());
+        if slot <= last_slot {
+            continue;
+        }
+        if slot > last_slot + 1 {
+            println!("Missed {} slot(s) before slot {}", slot - last_slot - 1, slot);
+        }
+        last_slot = slot;
+
+        let evidence = bc.lock().unwrap().report_evidence(&keypair);
+        for tx in evidence {
+            network.broadcast_tx(tx).await;
+        }
+
+        let block = {
+            let mut bc_locked = bc.lock().unwrap();
+            let tip_hash = bc_locked.chain.back().unwrap().hash.clone();
+            if bc_locked.select_validator(&tip_hash, slot).as_ref() != Some(&validator) {
+                continue;
+            }
+            let block = match bc_locked.create_block(slot, &keypair) {
+                Some(block) => block,
+                None => {
+                    println!("Slot {}: no pending transactions, skipping", slot);
+                    continue;
+                }
+            };
+            if !bc_locked.apply_block(block.clone()) {
+                // The transactions are still in the pool for a later slot
+                println!("Slot {}: failed to apply own block", slot);
+                continue;
+            }
+            println!(
+                "Slot {}: produced block {} with {} transaction(s), {} left in pool",
+                slot,
+                block.index,
+                block.transactions.len(),
+                bc_locked.mempool.len()
+            );
+            block
+        };
+
+        network.broadcast_block(block).await;
+    }
+}
@@ -0,0 +1,73 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use crate::chain_id::ChainId;
+use crate::genesis::GenesisSpec;
+
+// Network a node joins when none is named
+pub const DEFAULT_NETWORK: ChainId = ChainId::Devnet;
+
+/// Everything a node needs to join one of the Cacia networks.
+///
+/// Each profile carries the chain id, where its genesis spec comes from (and
+/// with it the protocol parameters), the default P2P and API addresses, the
+/// peers to bootstrap from and a data directory of its own, so nodes for
+/// different networks can run side by side on one machine.
+#[derive(Debug, Clone)]
+pub struct NetworkProfile {
+    pub chain_id: ChainId,
+    pub p2p_addr: String,
+    pub api_addr: SocketAddr,
+    pub seed_peers: Vec<String>,
+    pub data_dir: PathBuf,
+}
+
+impl NetworkProfile {
+    /// Profile for a network by name: `mainnet`, `testnet` or `devnet`.
+    pub fn new(name: &str) -> Result<Self, String> {
+        Ok(Self::for_chain(name.parse()?))
+    }
+
+    pub fn for_chain(chain_id: ChainId) -> Self {
+        // Seed peers are published with each network's genesis; none are live yet
+        let (p2p_addr, api_port, seed_peers) = match chain_id {
+            ChainId::Mainnet => ("0.0.0.0:27878", 28000, vec![]),
+            ChainId::Testnet => ("0.0.0.0:17878", 18000, vec![]),
+            // Local-only, on the ports the wallet tools talk to by default
+            ChainId::Devnet => ("127.0.0.1:7878", 8000, vec![]),
+        };
+        NetworkProfile {
+            chain_id,
+            p2p_addr: p2p_addr.to_string(),
+            api_addr: SocketAddr::from(([127, 0, 0, 1], api_port)),
+            seed_peers,
+            data_dir: PathBuf::from("./data").join(chain_id.name()),
+        }
+    }
+
+    /// Apply the `CACIA_P2P_ADDR`, `CACIA_API_ADDR`, `CACIA_DATA_DIR` and
+    /// `CACIA_PEERS` (comma separated, added to the seeds) overrides.
+    pub fn with_env_overrides(mut self) -> Result<Self, String> {
+        if let Ok(addr) = std::env::var("CACIA_P2P_ADDR") {
+            self.p2p_addr = addr;
+        }
+        if let Ok(addr) = std::env::var("CACIA_API_ADDR") {
+            self.api_addr = addr.parse().map_err(|e| format!("CACIA_API_ADDR: {}", e))?;
+        }
+        if let Ok(dir) = std::env::var("CACIA_DATA_DIR") {
+            self.data_dir = PathBuf::from(dir);
+        }
+        if let Ok(list) = std::env::var("CACIA_PEERS") {
+            self.seed_peers.extend(list.split(',').map(|p| p.trim().to_string()).filter(|p| !p.is_empty()));
+        }
+        Ok(self)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.chain_id.name()
+    }
+
+    // The genesis spec for this network; a devnet generates one with `validator` staked
+    pub fn genesis(&self, validator: &str) -> Result<GenesisSpec, String> {
+        GenesisSpec::load(self.chain_id, &self.data_dir, validator)
+    }
+}
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use crate::amount::Amount;
+use crate::encoding::{Encode, Encoder};
+use crate::storage::StateDelta;
+use crate::tx_error::TxError;
+use crate::{Blockchain, TREASURY};
+
+/// How much a block's proposer is paid on top of the block's fees.
+///
+/// Fixed and decaying rewards mint new coins, so the issued supply grows past
+/// the genesis total; treasury emission pays out of the treasury account until
+/// it runs dry and never changes the supply. Rewards are shared with the
+/// proposer's delegators just like fees.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(tag = "schedule", rename_all = "snake_case")]
+pub enum RewardSchedule {
+    #[default]
+    None,
+    Fixed { per_block: Amount },
+    // Starts at `initial` and halves every `halving_interval` blocks
+    Decaying { initial: Amount, halving_interval: u64 },
+    Treasury { per_block: Amount },
+}
+
+impl RewardSchedule {
+    // Reward scheduled for the block at `height`, before a treasury's balance is taken into account
+    pub fn reward_at(&self, height: u64) -> Amount {
+        match self {
+            RewardSchedule::None => Amount::ZERO,
+            RewardSchedule::Fixed { per_block } | RewardSchedule::Treasury { per_block } => *per_block,
+            RewardSchedule::Decaying { initial, halving_interval } => {
+                let halvings = height.saturating_sub(1) / (*halving_interval).max(1);
+                Amount::from_base_units(initial.base_units().checked_shr(halvings.min(64) as u32).unwrap_or(0))
+            }
+        }
+    }
+
+    pub fn mints(&self) -> bool {
+        matches!(self, RewardSchedule::Fixed { .. } | RewardSchedule::Decaying { .. })
+    }
+}
+
+impl Encode for RewardSchedule {
+    fn encode_to(&self, enc: &mut Encoder) {
+        match self {
+            RewardSchedule::None => enc.u8(0),
+            RewardSchedule::Fixed { per_block } => {
+                enc.u8(1);
+                enc.u64(per_block.base_units());
+            }
+            RewardSchedule::Decaying { initial, halving_interval } => {
+                enc.u8(2);
+                enc.u64(initial.base_units());
+                enc.u64(*halving_interval);
+            }
+            RewardSchedule::Treasury { per_block } => {
+                enc.u8(3);
+                enc.u64(per_block.base_units());
+            }
+        }
+    }
+}
+
+impl Blockchain {
+    // Issue the reward for the block at `height` into the staged delta: minted, or taken from
+    // the treasury as far as its balance goes. Returns what the proposer is owed.
+    pub(crate) fn issue_reward(&self, delta: &mut StateDelta, height: u64) -> Result<Amount, TxError> {
+        let schedule = &self.params.reward;
+        let mut reward = schedule.reward_at(height);
+        if schedule.mints() {
+            delta.minted = delta.minted.checked_add(reward).ok_or(TxError::AmountOverflow)?;
+        } else if reward > Amount::ZERO {
+            let treasury = self.staged_balance(delta, TREASURY);
+            reward = reward.min(treasury);
+            delta.balances.insert(TREASURY.to_string(), treasury.checked_sub(reward).unwrap());
+        }
+        Ok(reward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{address, chain, extend, keypair, transfer};
+
+    #[test]
+    fn decaying_rewards_halve() {
+        let schedule = RewardSchedule::Decaying { initial: Amount::from_base_units(1_000), halving_interval: 10 };
+        assert_eq!(schedule.reward_at(1), Amount::from_base_units(1_000));
+        assert_eq!(schedule.reward_at(10), Amount::from_base_units(1_000));
+        assert_eq!(schedule.reward_at(11), Amount::from_base_units(500));
+        assert_eq!(schedule.reward_at(100), Amount::from_base_units(1));
+        assert_eq!(schedule.reward_at(10_000), Amount::ZERO);
+    }
+
+    #[test]
+    fn minted_and_treasury_rewards_keep_the_supply_balanced() {
+        let validator = keypair(1);
+        let alice = keypair(2);
+        let mut bc = chain(&[(&validator, 1_000_000)], &[(&alice, 1_000_000)]);
+        bc.params.reward = RewardSchedule::Fixed { per_block: Amount::from_base_units(50_000) };
+        assert!(extend(&mut bc, &validator, vec![transfer(&alice, &alice, 1, 0)]));
+        assert_eq!(bc.get_balance(&address(&validator)), Amount::from_base_units(60_000));
+        assert_eq!(bc.audit_supply().minted, Amount::from_base_units(50_000));
+
+        // The treasury pays what it has left, then nothing
+        let treasury = bc.get_balance(TREASURY);
+        bc.params.reward = RewardSchedule::Treasury { per_block: treasury.checked_add(Amount::from_base_units(1)).unwrap() };
+        assert!(extend(&mut bc, &validator, vec![transfer(&alice, &alice, 1, 1)]));
+        assert_eq!(bc.get_balance(TREASURY), Amount::ZERO);
+        assert!(extend(&mut bc, &validator, vec![transfer(&alice, &alice, 1, 2)]));
+        let expected = Amount::from_base_units(60_000 + 20_000).checked_add(treasury).unwrap();
+        assert_eq!(bc.get_balance(&address(&validator)), expected);
+        assert_eq!(bc.audit_supply().minted, Amount::from_base_units(50_000));
+    }
+}
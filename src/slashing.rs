@@ -0,0 +1,433 @@
+use ed25519_dalek::{Keypair, PublicKey, Signature, Verifier};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::address::Address;
+use crate::amount::Amount;
+use crate::chain_id::ChainId;
+use crate::encoding::{self, Decode, DecodeError, Decoder, Encode, Encoder, Versioned, BLOCK_VERSION, TX_VERSION};
+use crate::staking::TxKind;
+use crate::storage::StateDelta;
+use crate::tx_error::TxError;
+use crate::{fees, Block, Blockchain, Transaction, JAIL_PERIOD, SLASH_FRACTION_BPS};
+
+/// How equivocating validators are punished.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SlashingParams {
+    pub fraction_bps: u32,  // Share of the offender's stake burned, in basis points
+    pub jail_period: u64,   // Blocks the offender is left out of leader selection
+}
+
+impl Default for SlashingParams {
+    fn default() -> Self {
+        SlashingParams { fraction_bps: SLASH_FRACTION_BPS, jail_period: JAIL_PERIOD }
+    }
+}
+
+impl Encode for SlashingParams {
+    fn encode_to(&self, enc: &mut Encoder) {
+        enc.u32(self.fraction_bps);
+        enc.u64(self.jail_period);
+    }
+}
+
+/// Everything in a block's header, including the proposer's signature but not
+/// the transactions, which it commits to through their root. Enough to check
+/// who proposed a block without holding the block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BlockHeader {
+    #[serde(default = "encoding::unversioned")]
+    pub version: u8,  // The block's layout version, which its hash is computed under
+    pub index: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+    pub previous_hash: String,
+    pub transactions_root: String,
+    pub validator: String,
+    pub public_key: String,
+    pub signature: String,
+}
+
+impl BlockHeader {
+    pub fn hash(&self) -> String {
+        hex::encode(Sha256::digest(self.header_bytes()))
+    }
+
+    // Check that the key belongs to the named validator and that it signed the header
+    pub fn verify_signature(&self, chain_id: ChainId) -> bool {
+        let public_key = match hex::decode(&self.public_key).ok().and_then(|b| PublicKey::from_bytes(&b).ok()) {
+            Some(pk) => pk,
+            None => return false,
+        };
+        if Address::from_public_key(&public_key, chain_id).to_string() != self.validator {
+            return false;
+        }
+        match hex::decode(&self.signature).ok().and_then(|b| Signature::from_bytes(&b).ok()) {
+            Some(signature) => public_key.verify(&hex::decode(self.hash()).unwrap(), &signature).is_ok(),
+            None => false,
+        }
+    }
+}
+
+impl Encode for BlockHeader {
+    fn encode_to(&self, enc: &mut Encoder) {
+        enc.u64(self.index);
+        enc.u64(self.slot);
+        enc.i64(self.timestamp);
+        enc.str(&self.previous_hash);
+        enc.str(&self.transactions_root);
+        enc.str(&self.validator);
+        enc.str(&self.public_key);
+        enc.str(&self.signature);
+    }
+}
+
+impl Decode for BlockHeader {
+    fn decode_from(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(BlockHeader {
+            version: dec.version(),
+            index: dec.u64()?,
+            slot: dec.u64()?,
+            timestamp: dec.i64()?,
+            previous_hash: dec.str()?,
+            transactions_root: dec.str()?,
+            validator: dec.str()?,
+            public_key: dec.str()?,
+            signature: dec.str()?,
+        })
+    }
+}
+
+/// Proof that a validator signed two different blocks for the same slot.
+///
+/// The headers are kept in hash order, so the same pair of blocks always makes
+/// the same evidence whoever reports it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Evidence {
+    pub first: BlockHeader,
+    pub second: BlockHeader,
+}
+
+impl Evidence {
+    pub fn new(a: BlockHeader, b: BlockHeader) -> Self {
+        match a.hash() <= b.hash() {
+            true => Evidence { first: a, second: b },
+            false => Evidence { first: b, second: a },
+        }
+    }
+
+    pub fn offender(&self) -> &str {
+        &self.first.validator
+    }
+
+    pub fn slot(&self) -> u64 {
+        self.first.slot
+    }
+
+    // Height both blocks were proposed at; `verify` only accepts headers at the same one
+    pub fn height(&self) -> u64 {
+        self.first.index
+    }
+
+    pub fn verify(&self, chain_id: ChainId) -> Result<(), String> {
+        let (a, b) = (&self.first, &self.second);
+        if a.validator != b.validator || a.slot != b.slot {
+            return Err("headers are from different proposers or slots".into());
+        }
+        if a.index != b.index {
+            return Err("headers are at different heights".into());
+        }
+        if a.hash() == b.hash() {
+            return Err("headers are for the same block".into());
+        }
+        if !a.verify_signature(chain_id) || !b.verify_signature(chain_id) {
+            return Err(format!("headers are not both signed by {}", a.validator));
+        }
+        Ok(())
+    }
+}
+
+impl Versioned for BlockHeader {
+    const LATEST: u8 = BLOCK_VERSION;
+
+    fn version(&self) -> u8 {
+        self.version
+    }
+}
+
+// Version 2 transactions carried the headers without their version, which was 2 for all of them
+impl Encode for Evidence {
+    fn encode_to(&self, enc: &mut Encoder) {
+        for header in [&self.first, &self.second] {
+            match enc.version() {
+                2 => header.encode_to(enc),
+                _ => enc.versioned(header),
+            }
+        }
+    }
+}
+
+impl Decode for Evidence {
+    fn decode_from(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let mut header = || match dec.version() {
+            2 => BlockHeader::decode_from(dec),
+            _ => dec.versioned(),
+        };
+        Ok(Evidence { first: header()?, second: header()? })
+    }
+}
+
+/// A slashed validator's record: the latest offence it was punished for and
+/// the height from which it can lead slots again.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Jail {
+    pub offence_slot: u64,
+    pub release_height: u64,
+}
+
+impl Blockchain {
+    // Next block's height, which jail terms are checked against
+    pub(crate) fn next_height(&self) -> u64 {
+        self.chain.back().map(|block| block.index + 1).unwrap_or(0)
+    }
+
+    pub fn is_jailed(&self, address: &str) -> bool {
+        self.jailed.get(address).is_some_and(|jail| jail.release_height > self.next_height())
+    }
+
+    /// Check a block received from a peer against the blocks we hold for a second
+    /// block its proposer signed for the same slot. Evidence found is kept for the
+    /// producer to report.
+    pub fn detect_equivocation(&mut self, block: &Block) -> Option<Evidence> {
+        if block.index == 0 || !block.verify_signature(self.chain_id) {
+            return None;
+        }
+        let conflicts = |other: &&Block| other.slot == block.slot && other.validator == block.validator && other.hash != block.hash;
+        let other = self
+            .chain
+            .iter()
+            .rev()
+            .take_while(|other| other.slot >= block.slot)
+            .find(conflicts)
+            .or_else(|| self.side_blocks.iter().find(conflicts))?;
+
+        let evidence = Evidence::new(other.header(), block.header());
+        let punished = self.jailed.get(evidence.offender()).is_some_and(|jail| jail.offence_slot >= evidence.slot());
+        if punished || self.pending_evidence.contains(&evidence) {
+            return None;
+        }
+        println!("Detected double proposal by {} in slot {}", evidence.offender(), evidence.slot());
+        self.pending_evidence.push(evidence.clone());
+        Some(evidence)
+    }
+
+    /// Turn the evidence detected so far into evidence transactions signed by
+    /// `keypair` and add them to the pool. Returns them for broadcasting.
+    pub fn report_evidence(&mut self, keypair: &Keypair) -> Vec<Transaction> {
+        let reporter = Address::from_public_key(&keypair.public, self.chain_id).to_string();
+        let mut reported = Vec::new();
+        for evidence in std::mem::take(&mut self.pending_evidence) {
+            let mut tx = Transaction {
+                version: TX_VERSION,
+                chain_id: self.chain_id,
+                receiver: evidence.offender().to_string(),
+                kind: TxKind::Evidence(Box::new(evidence)),
+                sender: reporter.clone(),
+                amount: Amount::ZERO,
+                fee: Amount::ZERO,
+                nonce: self.pending_nonce(&reporter),
+                signature: "0".repeat(128),  // Full length, so the fee covers the signature
+                timestamp: chrono::Utc::now().timestamp(),
+                public_key: hex::encode(keypair.public.as_bytes()),
+            };
+            tx.fee = self.fee_policy.minimum(fees::tx_size(&tx));
+            tx.sign(keypair);
+            match self.add_transaction(tx.clone()) {
+                Ok(()) => reported.push(tx),
+                Err(e) => println!("Could not report evidence against {}: {}", tx.receiver, e),
+            }
+        }
+        reported
+    }
+
+    fn check_evidence(&self, tx: &Transaction, evidence: &Evidence, jailed: Option<&Jail>) -> Result<(), TxError> {
+        evidence.verify(self.chain_id).map_err(|reason| TxError::InvalidEvidence { reason })?;
+        if tx.receiver != evidence.offender() {
+            return Err(TxError::InvalidEvidence { reason: "receiver must be the offending validator".into() });
+        }
+        if jailed.is_some_and(|jail| jail.offence_slot >= evidence.slot()) {
+            return Err(TxError::AlreadyPunished { validator: tx.receiver.clone(), slot: evidence.slot() });
+        }
+        Ok(())
+    }
+
+    // Pool admission checks for evidence transactions against the confirmed state
+    pub(crate) fn check_slashing(&self, tx: &Transaction) -> Result<(), TxError> {
+        match &tx.kind {
+            TxKind::Evidence(evidence) => self.check_evidence(tx, evidence, self.jailed.get(&tx.receiver)),
+            _ => unreachable!("only evidence transactions are checked here"),
+        }
+    }
+
+    // Execute an evidence transaction in a block at `height` against the staged delta: burn
+    // part of the offender's stake, and of anything it unstaked since the start of the epoch it
+    // offended in (that stake still weighed in the set that let it propose), and jail it.
+    // Leaves the delta unchanged if the transaction is invalid.
+    pub(crate) fn execute_evidence(&self, delta: &mut StateDelta, tx: &Transaction, height: u64) -> Result<(), TxError> {
+        let evidence = match &tx.kind {
+            TxKind::Evidence(evidence) => evidence,
+            _ => unreachable!("only evidence transactions are executed here"),
+        };
+        let jailed = delta.jailed.get(&tx.receiver).or_else(|| self.jailed.get(&tx.receiver));
+        self.check_evidence(tx, evidence, jailed)?;
+
+        let available = self.staged_balance(delta, &tx.sender);
+        let balance = available.checked_sub(tx.fee).ok_or(TxError::InsufficientBalance { required: tx.fee, available })?;
+        let fraction_bps = self.params.slashing.fraction_bps as u128;
+        let cut = |amount: Amount| Amount::from_base_units((amount.base_units() as u128 * fraction_bps / 10_000) as u64);
+        let stake = delta.stakes.get(&tx.receiver).copied().unwrap_or_else(|| self.get_stake(&tx.receiver));
+        let mut slashed = cut(stake);
+
+        // Unstaking during or after the offending epoch doesn't get the stake out of reach
+        let epoch_start = evidence.height() / self.params.epoch_length * self.params.epoch_length;
+        let mut unbonds = self.staged_unbonds(delta, &tx.receiver);
+        let mut slash_unbonds = false;
+        for unbond in unbonds.iter_mut().filter(|unbond| unbond.unbonded_at >= epoch_start) {
+            let unbond_cut = cut(unbond.amount);
+            unbond.amount = unbond.amount.checked_sub(unbond_cut).unwrap();
+            slashed = slashed.checked_add(unbond_cut).ok_or(TxError::AmountOverflow)?;
+            slash_unbonds = true;
+        }
+        unbonds.retain(|unbond| unbond.amount > Amount::ZERO);
+        let burned = delta.burned.checked_add(slashed).ok_or(TxError::AmountOverflow)?;
+
+        delta.balances.insert(tx.sender.clone(), balance);
+        delta.stakes.insert(tx.receiver.clone(), stake.checked_sub(cut(stake)).unwrap());
+        if slash_unbonds {
+            delta.unbonding.insert(tx.receiver.clone(), unbonds);
+        }
+        delta.burned = burned;
+        let jail = Jail { offence_slot: evidence.slot(), release_height: height + self.params.slashing.jail_period };
+        delta.jailed.insert(tx.receiver.clone(), jail);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{address, block_on, chain, extend, import, keypair, report, staking, transfer};
+
+    #[test]
+    fn double_proposals_are_slashed_and_jailed() {
+        let (offender, honest) = (keypair(1), keypair(2));
+        let alice = keypair(3);
+        let mut bc = chain(&[(&offender, 1_000_000), (&honest, 1_000_000)], &[(&alice, 1_000_000)]);
+        bc.params.slashing.jail_period = 3;
+
+        let tip = bc.chain.back().unwrap().clone();
+        let first = block_on(&bc, &tip, tip.slot, &offender, vec![]);
+        let second = block_on(&bc, &tip, tip.slot, &offender, vec![transfer(&alice, &honest, 1, 0)]);
+        assert!(import(&mut bc, &first));
+        let evidence = bc.detect_equivocation(&second).unwrap();
+        assert_eq!(bc.detect_equivocation(&second), None);
+
+        let tx = report(&alice, &evidence, 0);
+        assert!(bc.add_transaction(tx.clone()).is_ok());
+        assert!(extend(&mut bc, &honest, vec![tx]));
+        let offender_addr = address(&offender);
+        assert_eq!(bc.get_stake(&offender_addr), Amount::from_base_units(950_000));
+        assert_eq!(bc.burned, Amount::from_base_units(50_000));
+        assert!(bc.is_jailed(&offender_addr));
+        let tip = bc.chain.back().unwrap().hash.clone();
+        assert!((0..100).all(|slot| bc.select_validator(&tip, slot) != Some(offender_addr.clone())));
+
+        let again = report(&alice, &evidence, 1);
+        assert_eq!(
+            bc.add_transaction(again),
+            Err(TxError::AlreadyPunished { validator: offender_addr.clone(), slot: evidence.slot() })
+        );
+
+        // Jailed at height 2 for 3 blocks, so it may lead again from height 5
+        assert!(extend(&mut bc, &honest, vec![transfer(&alice, &honest, 1, 1)]));
+        assert!(extend(&mut bc, &honest, vec![transfer(&alice, &honest, 1, 2)]));
+        assert!(!bc.is_jailed(&offender_addr));
+        assert_eq!(bc.validator_weight(&offender_addr), Amount::from_base_units(950_000));
+        assert!(bc.audit_chain().is_ok());
+    }
+
+    #[test]
+    fn detected_double_proposals_are_reported_and_slashed() {
+        let (offender, honest) = (keypair(1), keypair(2));
+        let alice = keypair(3);
+        let mut bc = chain(&[(&offender, 1_000_000), (&honest, 1_000_000)], &[(&honest, 1_000_000), (&alice, 1_000_000)]);
+
+        let tip = bc.chain.back().unwrap().clone();
+        let first = block_on(&bc, &tip, tip.slot, &offender, vec![]);
+        let second = block_on(&bc, &tip, tip.slot, &offender, vec![transfer(&alice, &honest, 1, 0)]);
+        assert!(import(&mut bc, &first));
+        assert!(bc.detect_equivocation(&second).is_some());
+
+        // The node's own report pays the minimum for its signed size and gets into the pool
+        let reported = bc.report_evidence(&honest);
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].fee, bc.fee_policy.minimum(fees::tx_size(&reported[0])));
+        assert_eq!(bc.mempool.len(), 1);
+        assert!(extend(&mut bc, &honest, reported));
+        assert_eq!(bc.get_stake(&address(&offender)), Amount::from_base_units(950_000));
+        assert!(bc.is_jailed(&address(&offender)));
+    }
+
+    #[test]
+    fn stake_unbonded_after_the_offence_is_slashed_too() {
+        let (offender, honest) = (keypair(1), keypair(2));
+        let alice = keypair(3);
+        let mut bc = chain(&[(&offender, 1_000_000), (&honest, 1_000_000)], &[(&offender, 100_000), (&alice, 1_000_000)]);
+        let units = Amount::from_base_units;
+        let offender_addr = address(&offender);
+
+        // Unstaked at height 1, before the double proposal at height 2 but in the same epoch,
+        // so it still counted towards the offender's weight
+        assert!(extend(&mut bc, &honest, vec![staking(&offender, TxKind::Unstake, 100_000, 0)]));
+        let tip = bc.chain.back().unwrap().clone();
+        let first = block_on(&bc, &tip, tip.slot, &offender, vec![]);
+        let second = block_on(&bc, &tip, tip.slot, &offender, vec![transfer(&alice, &honest, 1, 0)]);
+        assert!(import(&mut bc, &first));
+        let evidence = bc.detect_equivocation(&second).unwrap();
+        assert_eq!(evidence.height(), 2);
+
+        // Trying to get the rest out before the evidence lands
+        assert!(extend(&mut bc, &honest, vec![staking(&offender, TxKind::Unstake, 400_000, 1)]));
+        assert!(extend(&mut bc, &honest, vec![report(&alice, &evidence, 0)]));
+        assert_eq!(bc.get_stake(&offender_addr), units(475_000));
+        let unbonds: Vec<(Amount, u64)> = bc.unbonds(&offender_addr).iter().map(|u| (u.amount, u.unbonded_at)).collect();
+        assert_eq!(unbonds, vec![(units(95_000), 1), (units(380_000), 3)]);
+        assert_eq!(bc.burned, units(25_000 + 5_000 + 20_000));
+        assert!(bc.audit_chain().is_ok());
+    }
+
+    #[test]
+    fn rejects_evidence_that_proves_nothing() {
+        let (offender, honest) = (keypair(1), keypair(2));
+        let alice = keypair(3);
+        let mut bc = chain(&[(&offender, 1_000_000), (&honest, 1_000_000)], &[(&alice, 1_000_000)]);
+        let tip = bc.chain.back().unwrap().clone();
+        let first = block_on(&bc, &tip, tip.slot, &offender, vec![]);
+        let later = block_on(&bc, &tip, first.slot, &offender, vec![]);
+
+        let different_slots = Evidence::new(first.header(), later.header());
+        assert!(matches!(bc.add_transaction(report(&alice, &different_slots, 0)), Err(TxError::InvalidEvidence { .. })));
+
+        let mut forged = later.header();
+        forged.slot = first.slot;
+        let unsigned = Evidence::new(first.header(), forged);
+        assert!(matches!(bc.add_transaction(report(&alice, &unsigned, 0)), Err(TxError::InvalidEvidence { .. })));
+
+        // Signed by the offender for the same slot, but a height apart
+        let mut higher = Block { index: first.index + 1, ..first.clone() };
+        higher.hash = Blockchain::hash_block(&higher);
+        higher.sign(&offender);
+        let different_heights = Evidence::new(first.header(), higher.header());
+        assert_eq!(different_heights.verify(bc.chain_id), Err("headers are at different heights".to_string()));
+        assert!(matches!(bc.add_transaction(report(&alice, &different_heights, 0)), Err(TxError::InvalidEvidence { .. })));
+    }
+}
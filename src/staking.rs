@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+use crate::amount::Amount;
+use crate::encoding::{Decode, DecodeError, Decoder, Encode, Encoder};
+use crate::slashing::Evidence;
+use crate::storage::StateDelta;
+use crate::tx_error::TxError;
+use crate::{Blockchain, Transaction};
+
+/// What a transaction does with its `amount`.
+///
+/// Transfers pay it to the receiver. The staking kinds act on the sender's
+/// own account, so they must name the sender as receiver: stake locks balance
+/// as stake, unstake starts unbonding stake, and withdraw returns unbonded
+/// stake to the balance once its unbonding period is over.
+///
+/// Delegate and undelegate instead name a validator as receiver and move stake
+/// into and out of its delegation pool; undelegated stake unbonds like unstaked
+/// stake does. Set commission fixes the cut of its delegators' fees the sender
+/// takes as a validator, in basis points.
+///
+/// Evidence carries proof that the receiver, a validator, signed two blocks
+/// for one slot, and gets it slashed and jailed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TxKind {
+    #[default]
+    Transfer,
+    Stake,
+    Unstake,
+    Withdraw,
+    Delegate,
+    Undelegate,
+    SetCommission { rate_bps: u32 },
+    Evidence(Box<Evidence>),
+}
+
+impl Encode for TxKind {
+    fn encode_to(&self, enc: &mut Encoder) {
+        enc.u8(match self {
+            TxKind::Transfer => 0,
+            TxKind::Stake => 1,
+            TxKind::Unstake => 2,
+            TxKind::Withdraw => 3,
+            TxKind::Delegate => 4,
+            TxKind::Undelegate => 5,
+            TxKind::SetCommission { .. } => 6,
+            TxKind::Evidence(_) => 7,
+        });
+        match self {
+            TxKind::SetCommission { rate_bps } => enc.u32(*rate_bps),
+            TxKind::Evidence(evidence) => evidence.encode_to(enc),
+            _ => {}
+        }
+    }
+}
+
+impl Decode for TxKind {
+    fn decode_from(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        match dec.u8()? {
+            0 => Ok(TxKind::Transfer),
+            1 => Ok(TxKind::Stake),
+            2 => Ok(TxKind::Unstake),
+            3 => Ok(TxKind::Withdraw),
+            4 => Ok(TxKind::Delegate),
+            5 => Ok(TxKind::Undelegate),
+            6 => Ok(TxKind::SetCommission { rate_bps: dec.u32()? }),
+            7 => Ok(TxKind::Evidence(Box::new(Evidence::decode_from(dec)?))),
+            tag => Err(DecodeError::UnknownTag(tag)),
+        }
+    }
+}
+
+/// Stake on its way back to a balance, withdrawable from `release_height` on.
+///
+/// Until then it can still be slashed for an offence committed at or before
+/// `unbonded_at`, the height of the block that unstaked it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Unbond {
+    pub amount: Amount,
+    pub release_height: u64,
+    #[serde(default)]
+    pub unbonded_at: u64,
+}
+
+/// An account's stake and unbonding state, as served by `/unbonds`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StakingStatus {
+    pub stake: Amount,
+    pub unbonding: Vec<Unbond>,
+    pub withdrawable: Amount,  // Released as of the next block
+}
+
+impl Transaction {
+    // What the transaction takes out of the sender's balance, fee included
+    pub fn balance_debit(&self) -> Option<Amount> {
+        match self.kind {
+            TxKind::Transfer | TxKind::Stake | TxKind::Delegate => self.amount.checked_add(self.fee),
+            TxKind::Unstake | TxKind::Withdraw | TxKind::Undelegate | TxKind::SetCommission { .. } | TxKind::Evidence(_) => {
+                Some(self.fee)
+            }
+        }
+    }
+}
+
+// Total of the unbonds released by `height`
+pub(crate) fn withdrawable(unbonds: &[Unbond], height: u64) -> Amount {
+    Amount::checked_sum(unbonds.iter().filter(|u| u.release_height <= height).map(|u| u.amount)).unwrap_or(Amount::MAX)
+}
+
+// Take `amount` out of the released unbonds, oldest first; the caller checks it is available
+fn take_released(unbonds: &mut Vec<Unbond>, mut amount: Amount, height: u64) {
+    unbonds.retain_mut(|unbond| {
+        if amount == Amount::ZERO || unbond.release_height > height {
+            return true;
+        }
+        let taken = unbond.amount.min(amount);
+        amount = amount.checked_sub(taken).unwrap();
+        unbond.amount = unbond.amount.checked_sub(taken).unwrap();
+        unbond.amount > Amount::ZERO
+    });
+}
+
+impl Blockchain {
+    pub fn get_stake(&self, address: &str) -> Amount {
+        self.stakes.get(address).copied().unwrap_or_default()
+    }
+
+    pub fn unbonds(&self, address: &str) -> &[Unbond] {
+        self.unbonding.get(address).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn staking_status(&self, address: &str) -> StakingStatus {
+        let next_height = self.next_height();
+        StakingStatus {
+            stake: self.get_stake(address),
+            unbonding: self.unbonds(address).to_vec(),
+            withdrawable: withdrawable(self.unbonds(address), next_height),
+        }
+    }
+
+    pub(crate) fn staged_balance(&self, delta: &StateDelta, address: &str) -> Amount {
+        delta.balances.get(address).copied().unwrap_or_else(|| self.get_balance(address))
+    }
+
+    pub(crate) fn staged_unbonds(&self, delta: &StateDelta, address: &str) -> Vec<Unbond> {
+        delta.unbonding.get(address).cloned().unwrap_or_else(|| self.unbonds(address).to_vec())
+    }
+
+    // Pool admission checks for staking and delegation transactions against the confirmed state
+    pub(crate) fn check_staking(&self, tx: &Transaction) -> Result<(), TxError> {
+        if matches!(tx.kind, TxKind::Delegate | TxKind::Undelegate | TxKind::SetCommission { .. }) {
+            return self.check_delegation(tx);
+        }
+        if let TxKind::Evidence(_) = tx.kind {
+            return self.check_slashing(tx);
+        }
+        if tx.kind != TxKind::Transfer && tx.receiver != tx.sender {
+            return Err(TxError::ReceiverNotSender);
+        }
+        match tx.kind {
+            TxKind::Unstake => {
+                let available = self.get_stake(&tx.sender);
+                if available < tx.amount {
+                    return Err(TxError::InsufficientStake { required: tx.amount, available });
+                }
+            }
+            TxKind::Withdraw => {
+                let available = self.staking_status(&tx.sender).withdrawable;
+                if available < tx.amount {
+                    return Err(TxError::NotUnbonded { required: tx.amount, available });
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Execute a stake, unstake or withdraw in a block at `height` against the staged delta.
+    // Leaves the delta unchanged if the transaction is invalid.
+    pub(crate) fn execute_staking(&self, delta: &mut StateDelta, tx: &Transaction, height: u64) -> Result<(), TxError> {
+        if tx.receiver != tx.sender {
+            return Err(TxError::ReceiverNotSender);
+        }
+        let mut balance = self.staged_balance(delta, &tx.sender);
+        let mut stake = delta.stakes.get(&tx.sender).copied().unwrap_or_else(|| self.get_stake(&tx.sender));
+        let mut unbonds = self.staged_unbonds(delta, &tx.sender);
+
+        match tx.kind {
+            TxKind::Stake => {
+                stake = stake.checked_add(tx.amount).ok_or(TxError::AmountOverflow)?;
+            }
+            TxKind::Unstake => {
+                stake = stake
+                    .checked_sub(tx.amount)
+                    .ok_or(TxError::InsufficientStake { required: tx.amount, available: stake })?;
+                unbonds.push(Unbond { amount: tx.amount, release_height: height + self.params.unbonding_period, unbonded_at: height });
+            }
+            TxKind::Withdraw => {
+                let available = withdrawable(&unbonds, height);
+                if available < tx.amount {
+                    return Err(TxError::NotUnbonded { required: tx.amount, available });
+                }
+                take_released(&mut unbonds, tx.amount, height);
+                balance = balance.checked_add(tx.amount).ok_or(TxError::AmountOverflow)?;
+            }
+            _ => unreachable!("only stake, unstake and withdraw are executed here"),
+        }
+        let debit = tx.balance_debit().ok_or(TxError::AmountOverflow)?;
+        balance = balance.checked_sub(debit).ok_or(TxError::InsufficientBalance { required: debit, available: balance })?;
+
+        // The fee is paid out to the proposer with the rest of the block's fees
+        delta.balances.insert(tx.sender.clone(), balance);
+        delta.stakes.insert(tx.sender.clone(), stake);
+        delta.unbonding.insert(tx.sender.clone(), unbonds);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{address, chain, extend, keypair, staking, transfer};
+
+    #[test]
+    fn stake_unbond_and_withdraw() {
+        let validator = keypair(1);
+        let alice = keypair(2);
+        // Far more stake than alice will lock, so the validator leads nearly every slot
+        let mut bc = chain(&[(&validator, 100_000_000)], &[(&alice, 1_000_000)]);
+        bc.params.unbonding_period = 2;
+        let units = Amount::from_base_units;
+
+        assert!(extend(&mut bc, &validator, vec![staking(&alice, TxKind::Stake, 400_000, 0)]));
+        assert_eq!(bc.get_balance(&address(&alice)), units(590_000));
+        assert_eq!(bc.get_stake(&address(&alice)), units(400_000));
+
+        // Unstaked at height 2, so released at height 4
+        assert!(extend(&mut bc, &validator, vec![staking(&alice, TxKind::Unstake, 150_000, 1)]));
+        let status = bc.staking_status(&address(&alice));
+        assert_eq!(status.stake, units(250_000));
+        assert_eq!(status.unbonding, vec![Unbond { amount: units(150_000), release_height: 4, unbonded_at: 2 }]);
+        assert_eq!(status.withdrawable, Amount::ZERO);
+
+        let early = staking(&alice, TxKind::Withdraw, 100_000, 2);
+        assert_eq!(
+            bc.add_transaction(early.clone()),
+            Err(TxError::NotUnbonded { required: units(100_000), available: Amount::ZERO })
+        );
+        assert!(!extend(&mut bc, &validator, vec![early]));
+
+        assert!(extend(&mut bc, &validator, vec![transfer(&alice, &validator, 1, 2)]));
+        assert_eq!(bc.staking_status(&address(&alice)).withdrawable, units(150_000));
+        assert!(extend(&mut bc, &validator, vec![staking(&alice, TxKind::Withdraw, 100_000, 3)]));
+        assert_eq!(bc.get_balance(&address(&alice)), units(590_000 - 10_000 - 10_001 + 100_000 - 10_000));
+        assert_eq!(bc.unbonds(&address(&alice)), &[Unbond { amount: units(50_000), release_height: 4, unbonded_at: 2 }]);
+        assert!(bc.audit_chain().is_ok());
+    }
+
+    #[test]
+    fn staking_acts_only_on_the_senders_account() {
+        let validator = keypair(1);
+        let (alice, bob) = (keypair(2), keypair(3));
+        let mut bc = chain(&[(&validator, 100)], &[(&alice, 1_000_000)]);
+
+        let mut tx = staking(&alice, TxKind::Stake, 1_000, 0);
+        tx.receiver = address(&bob);
+        tx.sign(&alice);
+        assert_eq!(bc.add_transaction(tx.clone()), Err(TxError::ReceiverNotSender));
+        assert!(!extend(&mut bc, &validator, vec![tx]));
+
+        let unstake = staking(&alice, TxKind::Unstake, 1_000, 0);
+        assert_eq!(
+            bc.add_transaction(unstake),
+            Err(TxError::InsufficientStake { required: Amount::from_base_units(1_000), available: Amount::ZERO })
+        );
+    }
+}
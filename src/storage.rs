@@ -0,0 +1,543 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use std::error::Error;
+use std::path::Path;
+use sled::{Db, Transactional, Tree};
+use sled::transaction::{ConflictableTransactionError, TransactionalTree, UnabortableTransactionError};
+use crate::Block;
+use crate::amount::Amount;
+use crate::chain_id::ChainId;
+use crate::delegation::DelegationPool;
+use crate::epoch::ValidatorSet;
+use crate::encoding::Versioned;
+use crate::slashing::Jail;
+use crate::staking::Unbond;
+
+pub type StorageResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+// Account values touched by a block, written back in the same transaction as the block itself.
+// The full state at genesis is kept in the same shape so chains can be replayed from it.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct StateDelta {
+    pub balances: HashMap<String, Amount>,
+    pub stakes: HashMap<String, Amount>,
+    pub nonces: HashMap<String, u64>,
+    #[serde(default)]
+    pub unbonding: HashMap<String, Vec<Unbond>>,  // An empty list clears the account's entry
+    #[serde(default)]
+    pub delegations: HashMap<String, DelegationPool>,  // An empty pool clears the validator's entry
+    #[serde(default)]
+    pub jailed: HashMap<String, Jail>,
+    #[serde(default)]
+    pub validator_set: Option<ValidatorSet>,  // Set when the block closes an epoch
+    #[serde(default)]
+    pub chain_weight: u128,  // Cumulative fork-choice weight of the chain after this block
+    #[serde(default)]
+    pub burned: Amount,      // Total destroyed so far, counted by the supply audit
+    #[serde(default)]
+    pub minted: Amount,      // Total issued as block rewards so far
+}
+
+impl StateDelta {
+    // Apply a later block's delta on top of this full state, as apply_block does to the live one
+    pub fn merge(&mut self, delta: &StateDelta) {
+        self.balances.extend(delta.balances.iter().map(|(addr, amount)| (addr.clone(), *amount)));
+        self.stakes.extend(delta.stakes.iter().map(|(addr, amount)| (addr.clone(), *amount)));
+        self.nonces.extend(delta.nonces.iter().map(|(addr, nonce)| (addr.clone(), *nonce)));
+        for (addr, unbonds) in &delta.unbonding {
+            match unbonds.is_empty() {
+                true => self.unbonding.remove(addr),
+                false => self.unbonding.insert(addr.clone(), unbonds.clone()),
+            };
+        }
+        for (validator, pool) in &delta.delegations {
+            match pool.is_empty() {
+                true => self.delegations.remove(validator),
+                false => self.delegations.insert(validator.clone(), pool.clone()),
+            };
+        }
+        self.jailed.extend(delta.jailed.iter().map(|(addr, jail)| (addr.clone(), jail.clone())));
+        if let Some(set) = &delta.validator_set {
+            self.validator_set = Some(set.clone());
+        }
+        self.chain_weight = delta.chain_weight;
+        self.burned = delta.burned;
+        self.minted = delta.minted;
+    }
+}
+
+/// The stored values a reorg changes: each account the blocks it removes or
+/// adds touched, with its value on the new main chain or None where that has
+/// none, plus the chain-wide values.
+#[derive(Default)]
+pub struct StateDiff {
+    pub balances: HashMap<String, Option<Amount>>,
+    pub stakes: HashMap<String, Option<Amount>>,
+    pub nonces: HashMap<String, Option<u64>>,
+    pub unbonding: HashMap<String, Option<Vec<Unbond>>>,
+    pub delegations: HashMap<String, Option<DelegationPool>>,
+    pub jailed: HashMap<String, Option<Jail>>,
+    pub validator_set: ValidatorSet,
+    pub chain_weight: u128,
+    pub burned: Amount,
+    pub minted: Amount,
+}
+
+/// Sled-backed persistence for blocks, account state and lookup indexes.
+///
+/// Everything that belongs to one block is committed in a single multi-tree
+/// transaction, so a crash half-way through applying a block leaves the
+/// database exactly as it was at the previous block.
+#[derive(Clone)]
+pub struct Storage {
+    db: Db,
+    blocks: Tree,      // height (big-endian) -> canonically encoded block
+    block_index: Tree, // block hash -> height
+    tx_index: Tree,    // tx hash -> height
+    balances: Tree,
+    stakes: Tree,
+    nonces: Tree,
+    unbonding: Tree,   // address -> JSON list of unbonds
+    delegations: Tree, // validator address -> JSON delegation pool
+    jailed: Tree,      // validator address -> JSON jail record
+    meta: Tree,
+}
+
+impl Storage {
+    pub fn open<P: AsRef<Path>>(path: P) -> StorageResult<Self> {
+        let db = sled::open(path)?;
+        Ok(Self {
+            blocks: db.open_tree("blocks")?,
+            block_index: db.open_tree("block_index")?,
+            tx_index: db.open_tree("tx_index")?,
+            balances: db.open_tree("balances")?,
+            stakes: db.open_tree("stakes")?,
+            nonces: db.open_tree("nonces")?,
+            unbonding: db.open_tree("unbonding")?,
+            delegations: db.open_tree("delegations")?,
+            jailed: db.open_tree("jailed")?,
+            meta: db.open_tree("meta")?,
+            db,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn chain_id(&self) -> StorageResult<Option<ChainId>> {
+        match self.meta.get("chain_id")? {
+            Some(value) => {
+                let id = u32::from_be_bytes(value.as_ref().try_into().map_err(|_| "corrupt chain id in storage")?);
+                Ok(Some(ChainId::from_id(id).ok_or("unknown chain id in storage")?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn load_chain_weight(&self) -> StorageResult<u128> {
+        match self.meta.get("chain_weight")? {
+            Some(value) => Ok(u128::from_be_bytes(value.as_ref().try_into().map_err(|_| "corrupt chain weight in storage")?)),
+            None => Ok(0),
+        }
+    }
+
+    pub fn load_burned(&self) -> StorageResult<Amount> {
+        match self.meta.get("burned")? {
+            Some(value) => Ok(Amount::from_base_units(decode_u64(&value)?)),
+            None => Ok(Amount::ZERO),
+        }
+    }
+
+    pub fn load_minted(&self) -> StorageResult<Amount> {
+        match self.meta.get("minted")? {
+            Some(value) => Ok(Amount::from_base_units(decode_u64(&value)?)),
+            None => Ok(Amount::ZERO),
+        }
+    }
+
+    pub fn load_validator_set(&self) -> StorageResult<ValidatorSet> {
+        match self.meta.get("validator_set")? {
+            Some(value) => Ok(serde_json::from_slice(&value)?),
+            None => Err("validator set missing from storage".into()),
+        }
+    }
+
+    pub fn load_genesis_state(&self) -> StorageResult<StateDelta> {
+        match self.meta.get("genesis_state")? {
+            Some(value) => Ok(serde_json::from_slice(&value)?),
+            None => Err("genesis state missing from storage".into()),
+        }
+    }
+
+    pub fn load_chain(&self) -> StorageResult<Vec<Block>> {
+        let mut chain = Vec::new();
+        for entry in self.blocks.iter() {
+            let (_, value) = entry?;
+            chain.push(Block::decode(&value)?);
+        }
+        Ok(chain)
+    }
+
+    pub fn load_balances(&self) -> StorageResult<HashMap<String, Amount>> {
+        Self::load_map(&self.balances)
+    }
+
+    pub fn load_stakes(&self) -> StorageResult<HashMap<String, Amount>> {
+        Self::load_map(&self.stakes)
+    }
+
+    pub fn load_nonces(&self) -> StorageResult<HashMap<String, u64>> {
+        Self::load_map(&self.nonces)
+    }
+
+    pub fn load_unbonding(&self) -> StorageResult<HashMap<String, Vec<Unbond>>> {
+        Self::load_json_map(&self.unbonding)
+    }
+
+    pub fn load_delegations(&self) -> StorageResult<HashMap<String, DelegationPool>> {
+        Self::load_json_map(&self.delegations)
+    }
+
+    pub fn load_jailed(&self) -> StorageResult<HashMap<String, Jail>> {
+        Self::load_json_map(&self.jailed)
+    }
+
+    fn load_json_map<V: DeserializeOwned>(tree: &Tree) -> StorageResult<HashMap<String, V>> {
+        let mut map = HashMap::new();
+        for entry in tree.iter() {
+            let (key, value) = entry?;
+            map.insert(String::from_utf8(key.to_vec())?, serde_json::from_slice(&value)?);
+        }
+        Ok(map)
+    }
+
+    fn load_map<V: AccountValue>(tree: &Tree) -> StorageResult<HashMap<String, V>> {
+        let mut map = HashMap::new();
+        for entry in tree.iter() {
+            let (key, value) = entry?;
+            map.insert(String::from_utf8(key.to_vec())?, V::from_u64(decode_u64(&value)?));
+        }
+        Ok(map)
+    }
+
+    pub fn block_by_hash(&self, hash: &str) -> StorageResult<Option<Block>> {
+        match self.block_index.get(hash.as_bytes())? {
+            Some(height) => self.block_at(&height),
+            None => Ok(None),
+        }
+    }
+
+    pub fn block_by_tx(&self, tx_hash: &str) -> StorageResult<Option<Block>> {
+        match self.tx_index.get(tx_hash.as_bytes())? {
+            Some(height) => self.block_at(&height),
+            None => Ok(None),
+        }
+    }
+
+    fn block_at(&self, height: &[u8]) -> StorageResult<Option<Block>> {
+        match self.blocks.get(height)? {
+            Some(value) => Ok(Some(Block::decode(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Atomically write a block, its indexes and the account values it changed.
+    pub fn commit_block(&self, block: &Block, delta: &StateDelta) -> StorageResult<()> {
+        self.commit(block, delta, &[])
+    }
+
+    fn commit(&self, block: &Block, delta: &StateDelta, meta_entries: &[(&str, Vec<u8>)]) -> StorageResult<()> {
+        let height = block.index.to_be_bytes();
+        let encoded = block.encode();
+        let tx_hashes: Vec<String> = block.transactions.iter().map(|tx| hex::encode(tx.hash())).collect();
+
+        let unbonding = encode_entries(&delta.unbonding, Vec::is_empty)?;
+        let delegations = encode_entries(&delta.delegations, DelegationPool::is_empty)?;
+        let jailed = encode_entries(&delta.jailed, |_| false)?;
+        let validator_set = delta.validator_set.as_ref().map(serde_json::to_vec).transpose()?;
+
+        (&self.blocks, &self.block_index, &self.tx_index, &self.balances, &self.stakes, &self.nonces, &self.unbonding, &self.delegations, &self.jailed, &self.meta)
+            .transaction(|(blocks, block_index, tx_index, balances, stakes, nonces, unbonding_tree, delegations_tree, jailed_tree, meta)| {
+                blocks.insert(&height, encoded.as_slice())?;
+                block_index.insert(block.hash.as_bytes(), &height)?;
+                for tx_hash in &tx_hashes {
+                    tx_index.insert(tx_hash.as_bytes(), &height)?;
+                }
+                write_accounts(balances, &delta.balances)?;
+                write_accounts(stakes, &delta.stakes)?;
+                write_accounts(nonces, &delta.nonces)?;
+                write_entries(unbonding_tree, &unbonding)?;
+                write_entries(delegations_tree, &delegations)?;
+                write_entries(jailed_tree, &jailed)?;
+                if let Some(set) = &validator_set {
+                    meta.insert("validator_set", set.as_slice())?;
+                }
+                meta.insert("chain_weight", &delta.chain_weight.to_be_bytes())?;
+                meta.insert("burned", &delta.burned.base_units().to_be_bytes())?;
+                meta.insert("minted", &delta.minted.base_units().to_be_bytes())?;
+                for (key, value) in meta_entries {
+                    meta.insert(*key, value.as_slice())?;
+                }
+                Ok::<(), ConflictableTransactionError<()>>(())
+            })
+            .map_err(|e| format!("block {} commit failed: {:?}", block.index, e))?;
+
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Atomically write the genesis block, the network it belongs to and the full
+    /// genesis state, both as live account values and as the snapshot chains are
+    /// replayed from. Only valid while genesis is the tip.
+    pub fn commit_genesis(&self, genesis: &Block, chain_id: ChainId, state: &StateDelta) -> StorageResult<()> {
+        let meta_entries = [
+            ("chain_id", chain_id.id().to_be_bytes().to_vec()),
+            ("genesis_state", serde_json::to_vec(state)?),
+        ];
+        self.commit(genesis, state, &meta_entries)
+    }
+
+    /// Atomically move the stored main chain onto another branch: remove the
+    /// displaced blocks and their indexes, write the added ones, and bring the
+    /// accounts either touched up to date.
+    pub fn reorganise(&self, displaced: &[Block], added: &[Block], diff: &StateDiff) -> StorageResult<()> {
+        let removed: Vec<(u64, String, Vec<String>)> = displaced
+            .iter()
+            .map(|block| (block.index, block.hash.clone(), block.transactions.iter().map(|tx| hex::encode(tx.hash())).collect()))
+            .collect();
+        let encoded: Vec<(u64, Vec<u8>, String, Vec<String>)> = added
+            .iter()
+            .map(|block| {
+                let tx_hashes = block.transactions.iter().map(|tx| hex::encode(tx.hash())).collect();
+                (block.index, block.encode(), block.hash.clone(), tx_hashes)
+            })
+            .collect();
+        let balances = encode_diff(&diff.balances, |amount| Ok(amount.to_u64().to_be_bytes().to_vec()))?;
+        let stakes = encode_diff(&diff.stakes, |amount| Ok(amount.to_u64().to_be_bytes().to_vec()))?;
+        let nonces = encode_diff(&diff.nonces, |nonce| Ok(nonce.to_be_bytes().to_vec()))?;
+        let unbonding = encode_diff(&diff.unbonding, |unbonds| Ok(serde_json::to_vec(unbonds)?))?;
+        let delegations = encode_diff(&diff.delegations, |pool| Ok(serde_json::to_vec(pool)?))?;
+        let jailed = encode_diff(&diff.jailed, |jail| Ok(serde_json::to_vec(jail)?))?;
+        let validator_set = serde_json::to_vec(&diff.validator_set)?;
+
+        (&self.blocks, &self.block_index, &self.tx_index, &self.balances, &self.stakes, &self.nonces, &self.unbonding, &self.delegations, &self.jailed, &self.meta)
+            .transaction(|(blocks, block_index, tx_index, balances_tree, stakes_tree, nonces_tree, unbonding_tree, delegations_tree, jailed_tree, meta)| {
+                // Removals first, so transactions both branches include stay indexed
+                for (index, hash, tx_hashes) in &removed {
+                    blocks.remove(&index.to_be_bytes())?;
+                    block_index.remove(hash.as_bytes())?;
+                    for tx_hash in tx_hashes {
+                        tx_index.remove(tx_hash.as_bytes())?;
+                    }
+                }
+                for (index, block, hash, tx_hashes) in &encoded {
+                    let height = index.to_be_bytes();
+                    blocks.insert(&height, block.as_slice())?;
+                    block_index.insert(hash.as_bytes(), &height)?;
+                    for tx_hash in tx_hashes {
+                        tx_index.insert(tx_hash.as_bytes(), &height)?;
+                    }
+                }
+                write_entries(balances_tree, &balances)?;
+                write_entries(stakes_tree, &stakes)?;
+                write_entries(nonces_tree, &nonces)?;
+                write_entries(unbonding_tree, &unbonding)?;
+                write_entries(delegations_tree, &delegations)?;
+                write_entries(jailed_tree, &jailed)?;
+                meta.insert("validator_set", validator_set.as_slice())?;
+                meta.insert("chain_weight", &diff.chain_weight.to_be_bytes())?;
+                meta.insert("burned", &diff.burned.base_units().to_be_bytes())?;
+                meta.insert("minted", &diff.minted.base_units().to_be_bytes())?;
+                Ok::<(), ConflictableTransactionError<()>>(())
+            })
+            .map_err(|e| format!("reorg commit failed: {:?}", e))?;
+
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+// Account values (balances, stakes, nonces) are all stored as big-endian u64s
+trait AccountValue: Copy {
+    fn to_u64(self) -> u64;
+    fn from_u64(value: u64) -> Self;
+}
+
+impl AccountValue for u64 {
+    fn to_u64(self) -> u64 {
+        self
+    }
+
+    fn from_u64(value: u64) -> Self {
+        value
+    }
+}
+
+impl AccountValue for Amount {
+    fn to_u64(self) -> u64 {
+        self.base_units()
+    }
+
+    fn from_u64(value: u64) -> Self {
+        Amount::from_base_units(value)
+    }
+}
+
+fn write_accounts<V: AccountValue>(tree: &TransactionalTree, values: &HashMap<String, V>) -> Result<(), UnabortableTransactionError> {
+    for (addr, value) in values {
+        tree.insert(addr.as_bytes(), &value.to_u64().to_be_bytes())?;
+    }
+    Ok(())
+}
+
+fn encode_entries<V: Serialize>(values: &HashMap<String, V>, is_empty: impl Fn(&V) -> bool) -> StorageResult<Vec<(String, Option<Vec<u8>>)>> {
+    let mut encoded = Vec::new();
+    for (addr, value) in values {
+        let value = match is_empty(value) {
+            true => None,
+            false => Some(serde_json::to_vec(value)?),
+        };
+        encoded.push((addr.clone(), value));
+    }
+    Ok(encoded)
+}
+
+// Values a reorg changed, encoded up front like the entries below; None removes the key
+fn encode_diff<V>(values: &HashMap<String, Option<V>>, encode: impl Fn(&V) -> StorageResult<Vec<u8>>) -> StorageResult<Vec<(String, Option<Vec<u8>>)>> {
+    let mut encoded = Vec::new();
+    for (addr, value) in values {
+        encoded.push((addr.clone(), value.as_ref().map(&encode).transpose()?));
+    }
+    Ok(encoded)
+}
+
+// Unbonding lists, delegation pools and jail records are serialised up front as JSON; empty ones are removed
+fn write_entries(tree: &TransactionalTree, entries: &[(String, Option<Vec<u8>>)]) -> Result<(), UnabortableTransactionError> {
+    for (addr, value) in entries {
+        match value {
+            Some(value) => tree.insert(addr.as_bytes(), value.as_slice())?,
+            None => tree.remove(addr.as_bytes())?,
+        };
+    }
+    Ok(())
+}
+
+fn decode_u64(bytes: &[u8]) -> StorageResult<u64> {
+    let array: [u8; 8] = bytes.try_into().map_err(|_| "corrupt u64 value in storage")?;
+    Ok(u64::from_be_bytes(array))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::staking::TxKind;
+    use crate::testutil::{address, block_on, extend, import, keypair, spec, staking, transfer};
+    use crate::Blockchain;
+
+    // A fresh database directory for one test
+    fn temp_db(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cacia-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    // A copy of the chain without its database handle, which would keep the database locked
+    fn snapshot(bc: &Blockchain) -> Blockchain {
+        Blockchain { storage: None, ..bc.clone() }
+    }
+
+    fn assert_same_state(bc: &Blockchain, other: &Blockchain) {
+        assert_eq!(bc.chain.iter().map(|b| &b.hash).collect::<Vec<_>>(), other.chain.iter().map(|b| &b.hash).collect::<Vec<_>>());
+        assert_eq!(bc.balances, other.balances);
+        assert_eq!(bc.stakes, other.stakes);
+        assert_eq!(bc.nonces, other.nonces);
+        assert_eq!(bc.unbonding, other.unbonding);
+        assert_eq!(bc.delegations, other.delegations);
+        assert_eq!(bc.validator_set, other.validator_set);
+        assert_eq!(bc.chain_weight, other.chain_weight);
+    }
+
+    #[test]
+    fn reopening_recovers_the_chain_and_state() {
+        let dir = temp_db("reopen");
+        let validator = keypair(1);
+        let alice = keypair(2);
+        let mut spec = spec(&[(&validator, 1_000_000)], &[(&alice, 10_000_000)]);
+        spec.params.epoch_length = 2;
+
+        let mut bc = Blockchain::open(&dir, &spec).unwrap();
+        assert!(extend(&mut bc, &validator, vec![transfer(&alice, &validator, 500, 0), staking(&alice, TxKind::Stake, 2_000_000, 1)]));
+        assert!(extend(&mut bc, &validator, vec![staking(&alice, TxKind::Unstake, 1_000_000, 2)]));
+        assert!(extend(&mut bc, &validator, vec![]));
+        let expected = snapshot(&bc);
+        drop(bc);
+
+        let reopened = Blockchain::open(&dir, &spec).unwrap();
+        assert_eq!(reopened.chain.len(), 4);
+        assert_eq!(reopened.confirmed_nonce(&address(&alice)), 3);
+        assert_eq!(reopened.validator_set.epoch, 2);
+        assert!(!reopened.unbonding.is_empty());
+        assert_same_state(&reopened, &expected);
+        assert!(reopened.find_block_by_tx(&hex::encode(expected.chain[1].transactions[0].hash())).is_some());
+        drop(reopened);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejected_blocks_leave_the_stored_state_untouched() {
+        let dir = temp_db("rejected");
+        let validator = keypair(1);
+        let alice = keypair(2);
+        let spec = spec(&[(&validator, 1_000_000)], &[(&alice, 1_000_000)]);
+
+        let mut bc = Blockchain::open(&dir, &spec).unwrap();
+        assert!(extend(&mut bc, &validator, vec![transfer(&alice, &validator, 500, 0)]));
+        let before = snapshot(&bc);
+
+        // The first transfer is fine on its own; the overspend rejects the whole block
+        let overspend = transfer(&alice, &validator, 5_000_000, 2);
+        assert!(!extend(&mut bc, &validator, vec![transfer(&alice, &validator, 500, 1), overspend]));
+        assert_same_state(&bc, &before);
+        drop(bc);
+
+        let reopened = Blockchain::open(&dir, &spec).unwrap();
+        assert_same_state(&reopened, &before);
+        assert_eq!(reopened.confirmed_nonce(&address(&alice)), 1);
+        drop(reopened);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reorgs_rewrite_only_what_the_branches_changed() {
+        let dir = temp_db("reorg");
+        let validator = keypair(1);
+        let (alice, bob, carol) = (keypair(2), keypair(3), keypair(4));
+        let spec = spec(&[(&validator, 1_000_000)], &[(&alice, 10_000_000), (&carol, 1_000_000)]);
+
+        let mut bc = Blockchain::open(&dir, &spec).unwrap();
+        let genesis = bc.chain[0].clone();
+        let a1 = block_on(&bc, &genesis, genesis.slot, &validator, vec![staking(&alice, TxKind::Stake, 2_000_000, 0)]);
+        assert!(import(&mut bc, &a1));
+        let a2 = block_on(&bc, &a1, a1.slot, &validator, vec![staking(&alice, TxKind::Unstake, 1_000_000, 1)]);
+        assert!(import(&mut bc, &a2));
+
+        // A heavier branch without alice's staking, which has to disappear from storage too
+        let b1 = block_on(&bc, &genesis, a2.slot, &validator, vec![transfer(&carol, &bob, 700, 0)]);
+        let b2 = block_on(&bc, &b1, b1.slot, &validator, vec![]);
+        let b3 = block_on(&bc, &b2, b2.slot, &validator, vec![]);
+        assert!(!import(&mut bc, &b1) && !import(&mut bc, &b2));
+        assert!(import(&mut bc, &b3));
+        assert!(!bc.stakes.contains_key(&address(&alice)) && bc.unbonding.is_empty());
+        let expected = snapshot(&bc);
+        drop(bc);
+
+        let reopened = Blockchain::open(&dir, &spec).unwrap();
+        assert_same_state(&reopened, &expected);
+        assert!(reopened.find_block_by_tx(&hex::encode(a1.transactions[0].hash())).is_none());
+        assert!(reopened.find_block_by_tx(&hex::encode(b1.transactions[0].hash())).is_some());
+        drop(reopened);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
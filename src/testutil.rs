@@ -0,0 +1,113 @@
+// Helpers for building chains, transactions and blocks in tests
+use std::collections::BTreeMap;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+use crate::address::Address;
+use crate::amount::Amount;
+use crate::chain_id::ChainId;
+use crate::encoding::{BLOCK_VERSION, TX_VERSION};
+use crate::genesis::GenesisSpec;
+use crate::slashing::Evidence;
+use crate::staking::TxKind;
+use crate::{Block, Blockchain, Transaction};
+
+pub fn keypair(seed: u8) -> Keypair {
+    let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+    let public = PublicKey::from(&secret);
+    Keypair { secret, public }
+}
+
+pub fn address(keypair: &Keypair) -> String {
+    Address::from_public_key(&keypair.public, ChainId::Devnet).to_string()
+}
+
+// Genesis an hour in the past so there are plenty of slots to build branches in
+pub fn spec(stakes: &[(&Keypair, u64)], balances: &[(&Keypair, u64)]) -> GenesisSpec {
+    let accounts = |list: &[(&Keypair, u64)]| -> BTreeMap<String, Amount> {
+        list.iter().map(|(kp, amount)| (address(kp), Amount::from_base_units(*amount))).collect()
+    };
+    let timestamp = chrono::Utc::now().timestamp() - 3600;
+    GenesisSpec::with_treasury(ChainId::Devnet, timestamp, accounts(stakes), accounts(balances)).unwrap()
+}
+
+pub fn chain(stakes: &[(&Keypair, u64)], balances: &[(&Keypair, u64)]) -> Blockchain {
+    Blockchain::from_genesis(&spec(stakes, balances))
+}
+
+pub fn transfer(from: &Keypair, to: &Keypair, amount: u64, nonce: u64) -> Transaction {
+    signed(from, TxKind::Transfer, &address(to), amount, nonce)
+}
+
+// A staking transaction on the sender's own account
+pub fn staking(from: &Keypair, kind: TxKind, amount: u64, nonce: u64) -> Transaction {
+    signed(from, kind, &address(from), amount, nonce)
+}
+
+// A delegation transaction from `from` to the validator `to`
+pub fn delegation(from: &Keypair, to: &Keypair, kind: TxKind, amount: u64, nonce: u64) -> Transaction {
+    signed(from, kind, &address(to), amount, nonce)
+}
+
+// An evidence transaction against the validator the evidence names, with a fee covering its size
+pub fn report(from: &Keypair, evidence: &Evidence, nonce: u64) -> Transaction {
+    let mut tx = signed(from, TxKind::Evidence(Box::new(evidence.clone())), evidence.offender(), 0, nonce);
+    tx.fee = Amount::from_base_units(100_000);
+    tx.sign(from);
+    tx
+}
+
+fn signed(from: &Keypair, kind: TxKind, receiver: &str, amount: u64, nonce: u64) -> Transaction {
+    let mut tx = Transaction {
+        version: TX_VERSION,
+        chain_id: ChainId::Devnet,
+        kind,
+        sender: address(from),
+        receiver: receiver.to_string(),
+        amount: Amount::from_base_units(amount),
+        fee: Amount::from_base_units(10_000),
+        nonce,
+        signature: String::new(),
+        timestamp: 0,
+        public_key: hex::encode(from.public.as_bytes()),
+    };
+    tx.sign(from);
+    tx
+}
+
+// Build a signed block on `parent` in the first slot after `after_slot` that `proposer`
+// leads, evaluated against `bc`'s stakes
+pub fn block_on(bc: &Blockchain, parent: &Block, after_slot: u64, proposer: &Keypair, txs: Vec<Transaction>) -> Block {
+    let validator = address(proposer);
+    let slot = (after_slot + 1..)
+        .find(|slot| bc.select_validator(&parent.hash, *slot).as_ref() == Some(&validator))
+        .unwrap();
+    let block = Block {
+        version: BLOCK_VERSION,
+        index: parent.index + 1,
+        slot,
+        timestamp: (slot * bc.params.block_time) as i64,
+        transactions: txs,
+        previous_hash: parent.hash.clone(),
+        hash: String::new(),
+        validator,
+        public_key: hex::encode(proposer.public.as_bytes()),
+        signature: String::new(),
+    };
+    let mut block = Block { hash: Blockchain::hash_block(&block), ..block };
+    block.sign(proposer);
+    block
+}
+
+// Import a block and check the supply still adds up, whatever the outcome
+pub fn import(bc: &mut Blockchain, block: &Block) -> bool {
+    let imported = bc.import_block(block.clone());
+    let audit = bc.audit_supply();
+    assert!(audit.balanced, "supply audit failed after block {}: {:?}", block.index, audit);
+    imported
+}
+
+// Extend the main chain with a block from `proposer`, as `import` does
+pub fn extend(bc: &mut Blockchain, proposer: &Keypair, txs: Vec<Transaction>) -> bool {
+    let tip = bc.chain.back().unwrap().clone();
+    let block = block_on(bc, &tip, tip.slot, proposer, txs);
+    import(bc, &block)
+}
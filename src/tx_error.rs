@@ -0,0 +1,77 @@
+use serde::Serialize;
+use thiserror::Error;
+use warp::http::StatusCode;
+use crate::amount::Amount;
+use crate::chain_id::ChainId;
+
+/// Why a transaction was not accepted into the pool.
+///
+/// Serialised for API clients with an `error` tag naming the variant and its
+/// fields alongside, e.g. `{"error":"wrong_nonce","expected":3,"got":5}`.
+#[derive(Error, Serialize, Debug, PartialEq)]
+#[serde(tag = "error", rename_all = "snake_case")]
+pub enum TxError {
+    #[error("transaction is signed for {found}, this node runs {expected}")]
+    WrongChain { expected: ChainId, found: ChainId },
+    #[error("invalid signature")]
+    BadSignature,
+    #[error("sender {sender} is not the address of the signing key")]
+    SenderKeyMismatch { sender: String },
+    #[error("malformed address {address}: {reason}")]
+    MalformedAddress { address: String, reason: String },
+    #[error("fee {fee} is below the minimum of {minimum} for this transaction")]
+    FeeTooLow { fee: Amount, minimum: Amount },
+    #[error("incorrect nonce: expected {expected}, got {got}")]
+    WrongNonce { expected: u64, got: u64 },
+    #[error("insufficient balance: {required} needed, {available} available")]
+    InsufficientBalance { required: Amount, available: Amount },
+    #[error("stake, unstake, withdraw and commission transactions must name the sender as receiver")]
+    ReceiverNotSender,
+    #[error("insufficient stake: {required} needed, {available} staked")]
+    InsufficientStake { required: Amount, available: Amount },
+    #[error("only {available} has finished unbonding, {required} requested")]
+    NotUnbonded { required: Amount, available: Amount },
+    #[error("{address} has no stake of its own to delegate to")]
+    NotAValidator { address: String },
+    #[error("insufficient delegation: {required} needed, {available} delegated")]
+    InsufficientDelegation { required: Amount, available: Amount },
+    #[error("commission of {rate_bps} basis points is over 100%")]
+    InvalidCommission { rate_bps: u32 },
+    #[error("invalid evidence: {reason}")]
+    InvalidEvidence { reason: String },
+    #[error("{validator} has already been punished for slot {slot}")]
+    AlreadyPunished { validator: String, slot: u64 },
+    #[error("amounts overflow")]
+    AmountOverflow,
+    #[error("transaction is already in the pool")]
+    Duplicate,
+    #[error("replacement fee must be at least {min_fee} (pending fee is {old_fee})")]
+    ReplacementUnderpriced { old_fee: Amount, min_fee: Amount },
+    #[error("pool is full and the fee rate is too low to evict anything")]
+    PoolFull,
+}
+
+impl TxError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            TxError::WrongChain { .. }
+            | TxError::BadSignature
+            | TxError::SenderKeyMismatch { .. }
+            | TxError::MalformedAddress { .. }
+            | TxError::ReceiverNotSender
+            | TxError::InvalidCommission { .. }
+            | TxError::InvalidEvidence { .. }
+            | TxError::AmountOverflow => StatusCode::BAD_REQUEST,
+            TxError::FeeTooLow { .. }
+            | TxError::WrongNonce { .. }
+            | TxError::InsufficientBalance { .. }
+            | TxError::InsufficientStake { .. }
+            | TxError::NotUnbonded { .. }
+            | TxError::NotAValidator { .. }
+            | TxError::InsufficientDelegation { .. }
+            | TxError::ReplacementUnderpriced { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            TxError::Duplicate | TxError::AlreadyPunished { .. } => StatusCode::CONFLICT,
+            TxError::PoolFull => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::PathBuf;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+use reqwest::Response;
+use serde::de::DeserializeOwned;
+use crate::address::Address;
+use crate::amount::Amount;
+use crate::api::AccountNonces;
+use crate::chain_id::ChainId;
+use crate::encoding::TX_VERSION;
+use crate::fees::FeeEstimate;
+use crate::profile::NetworkProfile;
+use crate::staking::TxKind;
+use crate::Transaction;
+
+// Where the wallet tools keep account keys
+pub const WALLET_DIR: &str = "./wallets";
+
+// Base URL of the node's HTTP API: `url` if given, else the network profile's address
+pub fn node_api(url: Option<&String>, chain_id: ChainId) -> Result<String, String> {
+    match url {
+        Some(url) => Ok(url.trim_end_matches('/').to_string()),
+        None => Ok(format!("http://{}", NetworkProfile::for_chain(chain_id).with_env_overrides()?.api_addr)),
+    }
+}
+
+// Path of a named wallet's private key, as written by `create_account`
+pub fn private_key_path(wallet_name: &str) -> PathBuf {
+    PathBuf::from(WALLET_DIR).join(format!("{}_private.key", wallet_name))
+}
+
+pub fn load_keypair(wallet_name: &str) -> Result<Keypair, String> {
+    let path = private_key_path(wallet_name);
+    let hex_key = std::fs::read_to_string(&path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let secret = hex::decode(hex_key.trim())
+        .ok()
+        .and_then(|bytes| SecretKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| format!("{} does not hold a valid private key", path.display()))?;
+    let public = PublicKey::from(&secret);
+    Ok(Keypair { secret, public })
+}
+
+// A reply's JSON body, or the node's message if it turned the request down
+async fn read_reply<T: DeserializeOwned>(response: Response) -> Result<T, String> {
+    if !response.status().is_success() {
+        let status = response.status();
+        let body: serde_json::Value = response.json().await.unwrap_or_default();
+        return Err(match body.get("message").and_then(|m| m.as_str()) {
+            Some(message) => message.to_string(),
+            None => format!("node replied {}", status),
+        });
+    }
+    response.json().await.map_err(|e| format!("unexpected reply from node: {}", e))
+}
+
+/// Fetch `path` from the node at `api` and decode its JSON reply.
+///
+/// Errors are ready to show: the node being unreachable, its own message if it
+/// turned the request down, or a reply that doesn't decode.
+pub async fn get<T: DeserializeOwned>(api: &str, path: &str) -> Result<T, String> {
+    let response = reqwest::get(format!("{}/{}", api, path))
+        .await
+        .map_err(|e| format!("could not reach node at {}: {}", api, e))?;
+    read_reply(response).await
+}
+
+pub async fn fetch_balance(api: &str, address: &Address) -> Result<Amount, String> {
+    get(api, &format!("balance/{}", address)).await
+}
+
+/// Sign a transfer of `amount` to `receiver` from the keypair's account and
+/// submit it to the node at `api`.
+///
+/// The nonce is the next free one the node knows of, pending transactions
+/// included, and the fee is the node's `normal` estimate.
+pub async fn send(api: &str, keypair: &Keypair, chain_id: ChainId, receiver: &str, amount: Amount) -> Result<Transaction, String> {
+    let receiver = Address::parse(receiver, chain_id).map_err(|e| format!("invalid receiver address: {}", e))?;
+    if amount == Amount::ZERO {
+        return Err("amount must be greater than zero".to_string());
+    }
+    let sender = Address::from_public_key(&keypair.public, chain_id);
+    let nonces: AccountNonces = get(api, &format!("nonce/{}", sender)).await?;
+    let fees: FeeEstimate = get(api, "fees").await?;
+
+    let mut tx = Transaction {
+        version: TX_VERSION,
+        chain_id,
+        kind: TxKind::Transfer,
+        sender: sender.to_string(),
+        receiver: receiver.to_string(),
+        amount,
+        fee: fees.normal,
+        nonce: nonces.pending,
+        signature: String::new(),
+        timestamp: chrono::Utc::now().timestamp(),
+        public_key: hex::encode(keypair.public.as_bytes()),
+    };
+    tx.sign(keypair);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/send", api))
+        .json(&tx)
+        .send()
+        .await
+        .map_err(|e| format!("could not reach node at {}: {}", api, e))?;
+    read_reply::<String>(response).await?;
+    Ok(tx)
+}